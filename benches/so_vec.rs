@@ -0,0 +1,370 @@
+// Copyright 2020 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0"
+//
+// This is part of mouse-sovec
+//
+//  mouse-sovec is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sovec is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sovec.  If not, see <http://www.gnu.org/licenses/>.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use mouse_sovec::SoVec;
+use smallvec::SmallVec;
+use std::alloc::System;
+
+/// Number of `u8` elements `SoVec<u8, _>` can hold without allocating on 64 bit targets.
+const INLINE_CAPACITY: usize = 31;
+
+type Small = SmallVec<[u8; INLINE_CAPACITY]>;
+
+/// Lengths to be benchmarked: the all-inline case and the spill case.
+const LENGTHS: [usize; 2] = [INLINE_CAPACITY, 8 * INLINE_CAPACITY];
+
+fn push(c: &mut Criterion) {
+    let mut group = c.benchmark_group("push");
+
+    for &len in LENGTHS.iter() {
+        group.bench_with_input(BenchmarkId::new("SoVec", len), &len, |b, &len| {
+            b.iter(|| {
+                let mut v = SoVec::<u8, System>::default();
+                for i in 0..len {
+                    v.reserve(1);
+                    unsafe { v.push(i as u8) };
+                }
+                black_box(v)
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("Vec", len), &len, |b, &len| {
+            b.iter(|| {
+                let mut v = Vec::<u8>::new();
+                for i in 0..len {
+                    v.push(i as u8);
+                }
+                black_box(v)
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("SmallVec", len), &len, |b, &len| {
+            b.iter(|| {
+                let mut v = Small::new();
+                for i in 0..len {
+                    v.push(i as u8);
+                }
+                black_box(v)
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn construct_drop(c: &mut Criterion) {
+    let mut group = c.benchmark_group("construct_drop");
+
+    for &len in LENGTHS.iter() {
+        group.bench_with_input(BenchmarkId::new("SoVec", len), &len, |b, &len| {
+            b.iter(|| {
+                let mut v = SoVec::<u8, System>::default();
+                v.extend(0..len as u8);
+                black_box(&v);
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("Vec", len), &len, |b, &len| {
+            b.iter(|| {
+                let mut v = Vec::<u8>::new();
+                v.extend(0..len as u8);
+                black_box(&v);
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("SmallVec", len), &len, |b, &len| {
+            b.iter(|| {
+                let mut v = Small::new();
+                v.extend(0..len as u8);
+                black_box(&v);
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn spill(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spill");
+
+    group.bench_function("SoVec", |b| {
+        b.iter(|| {
+            let mut v = SoVec::<u8, System>::default();
+            v.extend(0..INLINE_CAPACITY as u8);
+            v.extend(black_box(Some(0)));
+            black_box(v)
+        })
+    });
+
+    group.bench_function("Vec", |b| {
+        b.iter(|| {
+            let mut v = Vec::<u8>::new();
+            v.extend(0..INLINE_CAPACITY as u8);
+            v.extend(black_box(Some(0)));
+            black_box(v)
+        })
+    });
+
+    group.bench_function("SmallVec", |b| {
+        b.iter(|| {
+            let mut v = Small::new();
+            v.extend(0..INLINE_CAPACITY as u8);
+            v.extend(black_box(Some(0)));
+            black_box(v)
+        })
+    });
+
+    group.finish();
+}
+
+fn clone_iter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("clone_iter");
+
+    for &len in LENGTHS.iter() {
+        let mut so_vec = SoVec::<u8, System>::default();
+        so_vec.extend(0..len as u8);
+        group.bench_with_input(BenchmarkId::new("SoVec", len), &so_vec, |b, v| {
+            b.iter(|| v.clone().iter().map(|&i| i as usize).sum::<usize>())
+        });
+
+        let vec: Vec<u8> = (0..len as u8).collect();
+        group.bench_with_input(BenchmarkId::new("Vec", len), &vec, |b, v| {
+            b.iter(|| v.clone().iter().map(|&i| i as usize).sum::<usize>())
+        });
+
+        let small: Small = (0..len as u8).collect();
+        group.bench_with_input(BenchmarkId::new("SmallVec", len), &small, |b, v| {
+            b.iter(|| v.clone().iter().map(|&i| i as usize).sum::<usize>())
+        });
+    }
+
+    group.finish();
+}
+
+fn extend(c: &mut Criterion) {
+    let mut group = c.benchmark_group("extend");
+
+    for &len in LENGTHS.iter() {
+        let origin: Vec<u8> = (0..len as u8).collect();
+
+        group.bench_with_input(BenchmarkId::new("exact_size", len), &origin, |b, origin| {
+            b.iter_batched(
+                || origin.clone(),
+                |origin| {
+                    let mut v = SoVec::<u8, System>::default();
+                    v.extend(origin);
+                    black_box(v)
+                },
+                BatchSize::SmallInput,
+            )
+        });
+
+        group.bench_with_input(BenchmarkId::new("filtered", len), &origin, |b, origin| {
+            b.iter_batched(
+                || origin.clone(),
+                |origin| {
+                    let mut v = SoVec::<u8, System>::default();
+                    v.extend(origin.into_iter().filter(|_| true));
+                    black_box(v)
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+// Indexing in a loop is what to be benchmarked.
+#[allow(clippy::needless_range_loop)]
+fn index(c: &mut Criterion) {
+    let mut group = c.benchmark_group("index");
+
+    let mut v = SoVec::<usize, System>::default();
+    v.extend(0..1024);
+
+    group.bench_function("checked", |b| {
+        b.iter(|| {
+            let v = black_box(&v);
+            let mut sum = 0;
+            for i in 0..v.len() {
+                sum += v[i];
+            }
+            sum
+        })
+    });
+
+    group.bench_function("unchecked", |b| {
+        b.iter(|| {
+            let v = black_box(&v);
+            let mut sum = 0;
+            for i in 0..v.len() {
+                sum += unsafe { v.index_unchecked(i) };
+            }
+            sum
+        })
+    });
+
+    group.bench_function("as_slice", |b| {
+        b.iter(|| {
+            let v = black_box(&v).as_slice();
+            let mut sum = 0;
+            for i in 0..v.len() {
+                sum += v[i];
+            }
+            sum
+        })
+    });
+
+    group.finish();
+}
+
+fn eq(c: &mut Criterion) {
+    let mut group = c.benchmark_group("eq");
+
+    let mut v = SoVec::<u8, System>::default();
+    v.extend((0..1_usize << 20).map(|i| i as u8));
+    let w = v.clone();
+
+    group.bench_function("bytes_eq", |b| {
+        b.iter(|| unsafe { black_box(&v).bytes_eq(black_box(&w)) })
+    });
+
+    group.bench_function("element_wise", |b| {
+        b.iter(|| {
+            let (v, w) = (black_box(&v), black_box(&w));
+            v.len() == w.len() && v.iter().zip(w.iter()).all(|(a, b)| a == b)
+        })
+    });
+
+    group.finish();
+}
+
+fn write(c: &mut Criterion) {
+    use std::io::Write;
+
+    let chunk = [0_u8; 64];
+
+    c.bench_function("write", |b| {
+        b.iter(|| {
+            let mut v = SoVec::<u8, System>::default();
+            for _ in 0..((1 << 20) / chunk.len()) {
+                v.write_all(black_box(&chunk)).unwrap();
+            }
+            black_box(v)
+        })
+    });
+}
+
+fn from_exact_iter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("from_exact_iter");
+
+    for &len in LENGTHS.iter() {
+        let origin: Vec<u8> = (0..len as u8).collect();
+
+        group.bench_with_input(
+            BenchmarkId::new("from_exact_iter", len),
+            &origin,
+            |b, origin| {
+                b.iter_batched(
+                    || origin.clone(),
+                    |origin| black_box(SoVec::from_exact_iter(origin, System)),
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("from_iter_in", len),
+            &origin,
+            |b, origin| {
+                b.iter_batched(
+                    || origin.clone(),
+                    |origin| black_box(SoVec::from_iter_in(origin, System)),
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn clear(c: &mut Criterion) {
+    let mut group = c.benchmark_group("clear");
+
+    // `clear` does not depend on the length if the elements do not need drop.
+    for &len in [1_000, 1_000_000].iter() {
+        let mut origin = SoVec::<u8, System>::default();
+        origin.resize(len, 0);
+
+        group.bench_with_input(BenchmarkId::new("SoVec", len), &origin, |b, origin| {
+            b.iter_batched_ref(
+                || origin.clone(),
+                |v| {
+                    v.clear();
+                    black_box(v.len())
+                },
+                BatchSize::LargeInput,
+            )
+        });
+
+        let origin = vec![0_u8; len];
+        group.bench_with_input(BenchmarkId::new("Vec", len), &origin, |b, origin| {
+            b.iter_batched_ref(
+                || origin.clone(),
+                |v| {
+                    v.clear();
+                    black_box(v.len())
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    push,
+    construct_drop,
+    spill,
+    clone_iter,
+    extend,
+    index,
+    eq,
+    write,
+    from_exact_iter,
+    clear
+);
+criterion_main!(benches);
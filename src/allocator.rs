@@ -29,14 +29,27 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use core::sync::atomic::{AtomicI64, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering};
 use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::Mutex;
 
 /// Wrappter of `std::alloc::System` .
 /// It counts allocation and deallocation, and check the both
 /// numbers are same on drop.
+///
+/// If `set_failing(true)` is called, `alloc` and `realloc` fail until `set_failing(false)` is called.
+///
+/// It also checks that `dealloc` is called with the same layout as that passed to `alloc` .
+///
+/// `&TestAllocator` implements `GlobalAlloc` as well, so that the test can inspect the
+/// allocator after passing it to `SoVec` .
 pub struct TestAllocator {
     count: AtomicI64,
+    alloc_count: AtomicUsize,
+    realloc_count: AtomicUsize,
+    dealloc_count: AtomicUsize,
+    failing: AtomicBool,
+    layouts: Mutex<Vec<(usize, Layout)>>,
 }
 
 impl TestAllocator {
@@ -44,34 +57,118 @@ impl TestAllocator {
     pub const fn new() -> Self {
         Self {
             count: AtomicI64::new(0),
+            alloc_count: AtomicUsize::new(0),
+            realloc_count: AtomicUsize::new(0),
+            dealloc_count: AtomicUsize::new(0),
+            failing: AtomicBool::new(false),
+            layouts: Mutex::new(Vec::new()),
         }
     }
+
+    /// Returns how many times `alloc` has succeeded.
+    pub fn alloc_count(&self) -> usize {
+        self.alloc_count.load(Ordering::Relaxed)
+    }
+
+    /// Makes `alloc` and `realloc` fail if `failing` is true.
+    pub fn set_failing(&self, failing: bool) {
+        self.failing.store(failing, Ordering::Relaxed);
+    }
+
+    /// Returns how many times `realloc` has succeeded.
+    pub fn realloc_count(&self) -> usize {
+        self.realloc_count.load(Ordering::Relaxed)
+    }
+
+    /// Returns how many times `dealloc` has been called.
+    pub fn dealloc_count(&self) -> usize {
+        self.dealloc_count.load(Ordering::Relaxed)
+    }
 }
 
 unsafe impl GlobalAlloc for TestAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if self.failing.load(Ordering::Relaxed) {
+            return core::ptr::null_mut();
+        }
+
         let system = System;
         let ptr = system.alloc(layout);
 
         if !ptr.is_null() {
             self.count.fetch_add(1, Ordering::Acquire);
+            self.alloc_count.fetch_add(1, Ordering::Relaxed);
+            self.layouts.lock().unwrap().push((ptr as usize, layout));
         }
 
         ptr
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        assert_eq!(false, ptr.is_null());
+        assert!(!ptr.is_null());
 
         let system = System;
         let c = self.count.fetch_sub(1, Ordering::Release);
+        self.dealloc_count.fetch_add(1, Ordering::Relaxed);
 
         if c <= 0 {
             panic!("Calls dealloc() too many times");
         }
 
+        {
+            let mut layouts = self.layouts.lock().unwrap();
+            let index = layouts
+                .iter()
+                .position(|&(p, _)| p == ptr as usize)
+                .expect("Calls dealloc() with unknown pointer");
+            let (_, allocated) = layouts.swap_remove(index);
+            assert_eq!(allocated, layout);
+        }
+
         system.dealloc(ptr, layout);
     }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        assert!(!ptr.is_null());
+
+        if self.failing.load(Ordering::Relaxed) {
+            return core::ptr::null_mut();
+        }
+
+        let system = System;
+        let new_ptr = system.realloc(ptr, layout, new_size);
+
+        if !new_ptr.is_null() {
+            self.realloc_count.fetch_add(1, Ordering::Relaxed);
+
+            let mut layouts = self.layouts.lock().unwrap();
+            let entry = layouts
+                .iter_mut()
+                .find(|(p, _)| *p == ptr as usize)
+                .expect("Calls realloc() with unknown pointer");
+            assert_eq!(entry.1, layout);
+            *entry = (
+                new_ptr as usize,
+                Layout::from_size_align_unchecked(new_size, layout.align()),
+            );
+        }
+
+        new_ptr
+    }
+}
+
+unsafe impl GlobalAlloc for &TestAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        (*self).alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        (*self).dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        (*self).realloc(ptr, layout, new_size)
+    }
 }
 
 impl Drop for TestAllocator {
@@ -82,6 +179,18 @@ impl Drop for TestAllocator {
     }
 }
 
+unsafe impl crate::UsableSize for TestAllocator {}
+
+unsafe impl crate::UsableSize for &TestAllocator {}
+
+/// Creates a new instance instead of copying `self` , because each instance
+/// counts its own allocation and deallocation.
+impl Clone for TestAllocator {
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
 impl Default for TestAllocator {
     fn default() -> Self {
         Self::new()
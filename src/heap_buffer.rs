@@ -29,9 +29,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::try_reserve_error::TryReserveError;
+use crate::usable_size::UsableSize;
 use core::alloc::{GlobalAlloc, Layout};
 use core::mem::{align_of, size_of};
-use std::alloc::handle_alloc_error;
+use core::ptr::NonNull;
 
 pub struct HeapBuffer<T> {
     ptr: *mut T,
@@ -41,32 +43,87 @@ pub struct HeapBuffer<T> {
 
 impl<T> HeapBuffer<T> {
     /// Allocates heap memory using `alloc` and creates a new instance whose capacity is greater than or
-    /// equals to `capacity` .
+    /// equals to `capacity` , or returns an error if failed to allocate.
     ///
     /// # Safety
     ///
     /// `capacity` must not be 0.
-    pub unsafe fn with_capacity<A>(capacity: usize, alloc: &A) -> Self
+    pub unsafe fn try_with_capacity<A>(capacity: usize, alloc: &A) -> Result<Self, TryReserveError>
     where
         A: GlobalAlloc,
     {
         debug_assert_ne!(0, capacity);
 
-        let size = capacity
-            .checked_mul(size_of::<T>())
-            .expect("Allocating memory size is too large.");
+        // Zero sized type does not require any allocation.
+        if size_of::<T>() == 0 {
+            return Ok(Self {
+                ptr: NonNull::dangling().as_ptr(),
+                len_: 0,
+                cap_: capacity,
+            });
+        }
+
+        let size = Self::try_alloc_size(capacity)?;
         let align = align_of::<T>();
-        let layout = Layout::from_size_align(size, align).unwrap_or_else(|e| panic!("{}", e));
+        let layout =
+            Layout::from_size_align(size, align).map_err(|_| TryReserveError::CapacityOverflow)?;
 
         let ptr = alloc.alloc(layout) as *mut T;
         if ptr.is_null() {
-            handle_alloc_error(layout);
+            return Err(TryReserveError::AllocError { layout });
         }
 
-        Self {
+        Ok(Self {
             ptr,
             len_: 0,
             cap_: capacity,
+        })
+    }
+
+    /// Allocates heap memory as `try_with_capacity` does, and creates a new instance whose
+    /// capacity is as many as the allocated memory can hold according to `alloc.usable_size` .
+    ///
+    /// # Safety
+    ///
+    /// `capacity` must not be 0.
+    pub unsafe fn try_with_usable_capacity<A>(
+        capacity: usize,
+        alloc: &A,
+    ) -> Result<Self, TryReserveError>
+    where
+        A: UsableSize,
+    {
+        let mut ret = Self::try_with_capacity(capacity, alloc)?;
+
+        if size_of::<T>() != 0 {
+            let usable_size = alloc.usable_size(ret.ptr as *mut u8, ret.layout());
+            let usable_capacity = usable_size / size_of::<T>();
+            debug_assert!(capacity <= usable_capacity);
+            if Self::try_alloc_size(usable_capacity).is_ok() {
+                ret.cap_ = usable_capacity;
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Creates a new instance owning the heap memory `ptr` points to.
+    ///
+    /// # Safety
+    ///
+    /// - `ptr` must be allocated with the layout for `capacity` elements of `T` , and it will be
+    ///   deallocated by the allocator passed to `pre_drop` .
+    /// - The first `len` elements must be initialized.
+    /// - `capacity` must not be 0, and `len` must be less than or equal to `capacity` .
+    pub unsafe fn from_raw_parts(ptr: *mut T, len: usize, capacity: usize) -> Self {
+        debug_assert_ne!(0, capacity);
+        debug_assert!(len <= capacity);
+        debug_assert!(!ptr.is_null());
+
+        Self {
+            ptr,
+            len_: len,
+            cap_: capacity,
         }
     }
 
@@ -99,25 +156,74 @@ impl<T> HeapBuffer<T> {
     /// - The behavior is undefined if `new\_capacity` is 0.
     /// - `new_capacity` must be greater than or equals to `len` .
     pub unsafe fn set_capacity<A>(&mut self, new_capacity: usize, alloc: &A)
+    where
+        A: GlobalAlloc,
+    {
+        self.try_set_capacity(new_capacity, alloc)
+            .unwrap_or_else(|e| e.handle());
+    }
+
+    /// Reallocates the heap and forces the capacity of `self` to `new\_capacity` , or returns an
+    /// error if failed to reallocate.
+    ///
+    /// `self` is not changed on error.
+    ///
+    /// # Safety
+    ///
+    /// - The behavior is undefined if `new\_capacity` is 0.
+    /// - `new_capacity` must be greater than or equals to `len` .
+    pub unsafe fn try_set_capacity<A>(
+        &mut self,
+        new_capacity: usize,
+        alloc: &A,
+    ) -> Result<(), TryReserveError>
     where
         A: GlobalAlloc,
     {
         debug_assert_ne!(0, new_capacity);
         debug_assert!(self.len() <= new_capacity);
 
+        if size_of::<T>() == 0 {
+            self.cap_ = new_capacity;
+            return Ok(());
+        }
+
         let layout = self.layout();
-        let new_size = new_capacity
-            .checked_mul(size_of::<T>())
-            .expect("Allocating memory size is too large.");
-        let ptr = alloc.realloc(self.ptr as *mut u8, layout, new_size) as *mut T;
+        let new_size = Self::try_alloc_size(new_capacity)?;
+
+        // `realloc` may leave the old memory as it is, so the elements are moved by hand to zero
+        // the old memory before deallocation.
+        #[cfg(feature = "zeroize")]
+        {
+            let new_layout = Layout::from_size_align(new_size, layout.align())
+                .map_err(|_| TryReserveError::CapacityOverflow)?;
+            let ptr = alloc.alloc(new_layout) as *mut T;
+            if ptr.is_null() {
+                return Err(TryReserveError::AllocError { layout: new_layout });
+            }
+
+            core::ptr::copy_nonoverlapping(self.ptr, ptr, self.len());
+            crate::zeroize::zeroize(self.ptr as *mut u8, layout.size());
+            alloc.dealloc(self.ptr as *mut u8, layout);
 
-        if ptr.is_null() {
-            let layout = Layout::from_size_align(new_size, layout.align())
-                .unwrap_or_else(|e| panic!("{}", e));
-            handle_alloc_error(layout);
-        } else {
             self.ptr = ptr;
             self.cap_ = new_capacity;
+            Ok(())
+        }
+
+        #[cfg(not(feature = "zeroize"))]
+        {
+            let ptr = alloc.realloc(self.ptr as *mut u8, layout, new_size) as *mut T;
+
+            if ptr.is_null() {
+                let layout = Layout::from_size_align(new_size, layout.align())
+                    .map_err(|_| TryReserveError::CapacityOverflow)?;
+                Err(TryReserveError::AllocError { layout })
+            } else {
+                self.ptr = ptr;
+                self.cap_ = new_capacity;
+                Ok(())
+            }
         }
     }
 
@@ -146,8 +252,8 @@ impl<T> HeapBuffer<T> {
     where
         A: GlobalAlloc,
     {
-        unsafe {
-            alloc.dealloc(self.as_mut_ptr() as *mut u8, self.layout());
+        if size_of::<T>() != 0 {
+            unsafe { alloc.dealloc(self.as_mut_ptr() as *mut u8, self.layout()) };
         }
     }
 
@@ -167,15 +273,24 @@ impl<T> HeapBuffer<T> {
         A: GlobalAlloc,
     {
         assert_eq!(0, self.len());
-        assert_eq!(false, self.as_ptr().is_null());
+        assert!(!self.as_ptr().is_null());
 
-        unsafe {
-            alloc.dealloc(self.as_mut_ptr() as *mut u8, self.layout());
+        if size_of::<T>() != 0 {
+            unsafe { alloc.dealloc(self.as_mut_ptr() as *mut u8, self.layout()) };
         }
 
         self.ptr = core::ptr::null_mut();
     }
 
+    /// Returns the byte size to allocate to hold `capacity` elements, or returns an error if the
+    /// size exceeds `isize::MAX` , which `Layout` does not accept.
+    fn try_alloc_size(capacity: usize) -> Result<usize, TryReserveError> {
+        match capacity.checked_mul(size_of::<T>()) {
+            Some(size) if size <= isize::MAX as usize => Ok(size),
+            _ => Err(TryReserveError::CapacityOverflow),
+        }
+    }
+
     /// Returns the layout allocating the heap.
     fn layout(&self) -> Layout {
         let size = size_of::<T>() * self.capacity();
@@ -201,7 +316,7 @@ mod tests {
     fn constructor() {
         for i in 1..10 {
             let alloc = TestAllocator::new();
-            let mut b = unsafe { HeapBuffer::<String>::with_capacity(i, &alloc) };
+            let mut b = unsafe { HeapBuffer::<String>::try_with_capacity(i, &alloc).unwrap() };
 
             assert_eq!(0, b.len());
             assert!(i <= b.capacity());
@@ -211,7 +326,7 @@ mod tests {
 
         for i in 1..10 {
             let alloc = TestAllocator::new();
-            let mut b = unsafe { HeapBuffer::<u8>::with_capacity(i, &alloc) };
+            let mut b = unsafe { HeapBuffer::<u8>::try_with_capacity(i, &alloc).unwrap() };
 
             assert_eq!(0, b.len());
             assert!(i <= b.capacity());
@@ -224,7 +339,7 @@ mod tests {
     fn set_capacity() {
         for i in 1..10 {
             let alloc = TestAllocator::new();
-            let mut b = unsafe { HeapBuffer::<String>::with_capacity(i, &alloc) };
+            let mut b = unsafe { HeapBuffer::<String>::try_with_capacity(i, &alloc).unwrap() };
 
             for j in 1..10 {
                 unsafe { b.set_capacity(j, &alloc) };
@@ -235,4 +350,57 @@ mod tests {
             b.pre_drop(&alloc);
         }
     }
+
+    #[test]
+    fn zero_sized_type() {
+        let alloc = TestAllocator::new();
+        let mut b = unsafe { HeapBuffer::<()>::try_with_capacity(1, &alloc).unwrap() };
+        assert_eq!(0, b.len());
+        assert_eq!(1, b.capacity());
+
+        unsafe { b.set_capacity(1024, &alloc) };
+        assert_eq!(1024, b.capacity());
+
+        b.pre_drop(&alloc);
+    }
+
+    #[test]
+    fn try_with_usable_capacity() {
+        let alloc = TestAllocator::new();
+
+        // `TestAllocator` does not tell the usable size.
+        for capacity in 1..10 {
+            let mut buffer =
+                unsafe { HeapBuffer::<u64>::try_with_usable_capacity(capacity, &alloc).unwrap() };
+            assert_eq!(capacity, buffer.capacity());
+            buffer.pre_drop(&alloc);
+        }
+    }
+
+    #[test]
+    fn try_alloc_size() {
+        let max_capacity = isize::MAX as usize / size_of::<u64>();
+        assert_eq!(
+            Ok(max_capacity * size_of::<u64>()),
+            HeapBuffer::<u64>::try_alloc_size(max_capacity)
+        );
+        assert_eq!(Ok(0), HeapBuffer::<u64>::try_alloc_size(0));
+
+        assert_eq!(
+            Err(TryReserveError::CapacityOverflow),
+            HeapBuffer::<u64>::try_alloc_size(max_capacity + 1)
+        );
+        assert_eq!(
+            Err(TryReserveError::CapacityOverflow),
+            HeapBuffer::<u64>::try_alloc_size(usize::MAX)
+        );
+    }
+
+    #[test]
+    fn try_with_capacity_overflow() {
+        let alloc = TestAllocator::new();
+        let max_capacity = isize::MAX as usize / size_of::<u64>();
+        let result = unsafe { HeapBuffer::<u64>::try_with_capacity(max_capacity + 1, &alloc) };
+        assert!(matches!(result, Err(TryReserveError::CapacityOverflow)));
+    }
 }
@@ -0,0 +1,91 @@
+// Copyright 2020 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0"
+//
+// This is part of mouse-sovec
+//
+//  mouse-sovec is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sovec is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sovec.  If not, see <http://www.gnu.org/licenses/>.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::heap_buffer::HeapBuffer;
+
+/// The heap memory taken out of `SoVec` by `dismantle` .
+///
+/// It owns neither the memory nor the elements; i.e. dropping it leaks them.
+/// Pass it to `SoVec::from_heap_handle` to reuse them.
+///
+/// It also remembers the max capacity of the dismantled `SoVec` .
+#[derive(Debug)]
+pub struct HeapHandle<T> {
+    ptr: *mut T,
+    len: usize,
+    cap: usize,
+    max_capacity: usize,
+}
+
+impl<T> HeapHandle<T> {
+    /// Creates a new instance taking the pointer, the length, and the capacity of `buffer` .
+    pub(crate) fn new(buffer: HeapBuffer<T>, max_capacity: usize) -> Self {
+        let mut buffer = core::mem::ManuallyDrop::new(buffer);
+        Self {
+            ptr: buffer.as_mut_ptr(),
+            len: buffer.len(),
+            cap: buffer.capacity(),
+            max_capacity,
+        }
+    }
+
+    /// Creates `HeapBuffer` owning the memory `self` points to.
+    pub(crate) unsafe fn into_buffer(self) -> HeapBuffer<T> {
+        HeapBuffer::from_raw_parts(self.ptr, self.len, self.cap)
+    }
+
+    /// Returns the number of the initialized elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if no element is initialized.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of the elements the heap memory can hold.
+    pub fn capacity(&self) -> usize {
+        self.cap
+    }
+
+    /// Returns a raw pointer to the heap memory.
+    pub fn as_ptr(&self) -> *const T {
+        self.ptr
+    }
+
+    /// Returns the max capacity of the dismantled `SoVec` .
+    ///
+    /// It is always `usize::MAX` unless feature `max-capacity` is enabled.
+    pub fn max_capacity(&self) -> usize {
+        self.max_capacity
+    }
+}
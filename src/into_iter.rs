@@ -0,0 +1,212 @@
+// Copyright 2020 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0"
+//
+// This is part of mouse-sovec
+//
+//  mouse-sovec is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sovec is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sovec.  If not, see <http://www.gnu.org/licenses/>.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::so_vec::SoVec;
+use core::alloc::GlobalAlloc;
+use core::iter::FusedIterator;
+
+/// An iterator that moves out of `SoVec` .
+///
+/// This is created by `into_iter` method on `SoVec` (provided by `IntoIterator` trait.)
+pub struct IntoIter<T, A>
+where
+    A: GlobalAlloc,
+{
+    // The length of `vec` is always 0 so that the elements will not be dropped twice.
+    vec: SoVec<T, A>,
+    start: usize,
+    end: usize,
+}
+
+impl<T, A> IntoIter<T, A>
+where
+    A: GlobalAlloc,
+{
+    /// Creates a new instance.
+    pub(crate) fn new(mut vec: SoVec<T, A>) -> Self {
+        let end = vec.len();
+        unsafe { vec.set_len(0) };
+
+        Self { vec, start: 0, end }
+    }
+
+    /// Returns the remaining elements as a slice.
+    pub fn as_slice(&self) -> &[T] {
+        unsafe {
+            let ptr = self.vec.as_ptr().add(self.start);
+            core::slice::from_raw_parts(ptr, self.end - self.start)
+        }
+    }
+
+    /// Returns the remaining elements as a mutable slice.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe {
+            let ptr = self.vec.as_mut_ptr().add(self.start);
+            core::slice::from_raw_parts_mut(ptr, self.end - self.start)
+        }
+    }
+
+    /// Drops the remaining elements, deallocates the buffer, and returns the allocator.
+    ///
+    /// This enables to reuse the allocator after consuming the elements.
+    pub fn into_allocator(mut self) -> A {
+        self.drop_remaining();
+        let vec = unsafe { core::ptr::read(&self.vec) };
+        core::mem::forget(self);
+        vec.into_allocator()
+    }
+
+    /// Drops the remaining elements.
+    fn drop_remaining(&mut self) {
+        let remaining = self.as_mut_slice() as *mut [T];
+        self.start = self.end;
+        unsafe { core::ptr::drop_in_place(remaining) };
+    }
+}
+
+impl<T, A> Iterator for IntoIter<T, A>
+where
+    A: GlobalAlloc,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.start == self.end {
+            None
+        } else {
+            let ret = unsafe { core::ptr::read(self.vec.as_ptr().add(self.start)) };
+            self.start += 1;
+            Some(ret)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end - self.start;
+        (len, Some(len))
+    }
+}
+
+impl<T, A> DoubleEndedIterator for IntoIter<T, A>
+where
+    A: GlobalAlloc,
+{
+    fn next_back(&mut self) -> Option<T> {
+        if self.start == self.end {
+            None
+        } else {
+            self.end -= 1;
+            Some(unsafe { core::ptr::read(self.vec.as_ptr().add(self.end)) })
+        }
+    }
+}
+
+impl<T, A> ExactSizeIterator for IntoIter<T, A> where A: GlobalAlloc {}
+
+impl<T, A> FusedIterator for IntoIter<T, A> where A: GlobalAlloc {}
+
+impl<T, A> Drop for IntoIter<T, A>
+where
+    A: GlobalAlloc,
+{
+    fn drop(&mut self) {
+        self.drop_remaining();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::allocator::TestAllocator;
+
+    #[test]
+    fn next() {
+        for i in 0..100 {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend((0..i).map(|j| j.to_string()));
+
+            let mut it = v.into_iter();
+            for j in 0..i {
+                assert_eq!(i - j, it.len());
+                assert_eq!(Some(j.to_string()), it.next());
+            }
+            assert_eq!(None, it.next());
+        }
+    }
+
+    #[test]
+    fn next_back() {
+        for i in 0..100 {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend((0..i).map(|j| j.to_string()));
+
+            let mut it = v.into_iter();
+            for j in (0..i).rev() {
+                assert_eq!(Some(j.to_string()), it.next_back());
+            }
+            assert_eq!(None, it.next_back());
+        }
+    }
+
+    #[test]
+    fn drop_remaining() {
+        for i in 0..100 {
+            for j in 0..=i {
+                let mut v = SoVec::<String, TestAllocator>::default();
+                v.extend((0..i).map(|k| k.to_string()));
+
+                let mut it = v.into_iter();
+                for _ in 0..j {
+                    it.next();
+                }
+
+                let expected: Vec<String> = (j..i).map(|k| k.to_string()).collect();
+                assert_eq!(&expected[..], it.as_slice());
+            }
+        }
+    }
+
+    #[test]
+    fn into_allocator() {
+        let alloc = TestAllocator::new();
+        let mut v = SoVec::<String, &TestAllocator>::new(&alloc);
+        v.extend((0..100).map(|i| i.to_string()));
+
+        let mut it = v.into_iter();
+        let mut w = SoVec::<String, TestAllocator>::default();
+        w.extend(it.by_ref());
+        let alloc = it.into_allocator();
+
+        let mut u = SoVec::<String, &TestAllocator>::new(alloc);
+        u.extend(w.into_iter().rev());
+        let expected: Vec<String> = (0..100).rev().map(|i| i.to_string()).collect();
+        assert_eq!(&expected[..], u.as_ref());
+    }
+}
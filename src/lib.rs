@@ -41,12 +41,45 @@
 //! Instead of heap, it uses itself as a buffer then.
 //!
 //! To avoid allocating as much as possible, the performance is better than that of `std::collections::Vec` .
+//!
+//! # Features
+//!
+//! - `std` (default) : Enables `SoVec::into_arc_slice` .
+//! - `zeroize` : `SoVec` overwrites its buffer with zeros before the memory is released; i.e.
+//!   before the heap is deallocated or reallocated, and before the stack buffer is dropped or
+//!   abandoned for the heap. It is useful to handle secret data.
+//!   (Reallocation is done by `alloc` , copy, and `dealloc` then, because `realloc` may leave
+//!   the old memory as it is.)
+//! - `recording-allocator` : Exports `RecordingAllocator` , which records every allocation.
+//!   It is useful to write assertions about the allocation behavior of `SoVec` .
+//! - `debug-internals` : `{:#?}` of `SoVec` shows whether it has spilled and the capacity as well.
+//! - `max-capacity` : Enables `SoVec::set_max_capacity` to limit the capacity. It makes every
+//!   `SoVec` larger by `usize` to store the limit.
+//! - `spill-observer` : Enables `SoVec::set_spill_observer` to observe when `SoVec` starts to use
+//!   the heap. It makes every `SoVec` larger by the size of `Option<Box<dyn Fn(usize) + Send +
+//!   Sync>>` (2 words) to store the observer.
 
+mod global;
 mod heap_buffer;
+mod heap_handle;
+mod into_iter;
+#[cfg(feature = "recording-allocator")]
+mod recording_allocator;
 mod so_vec;
 mod stack_buffer;
+mod try_reserve_error;
+mod usable_size;
+#[cfg(feature = "zeroize")]
+mod zeroize;
 
+pub use global::Global;
+pub use heap_handle::HeapHandle;
+pub use into_iter::IntoIter;
+#[cfg(feature = "recording-allocator")]
+pub use recording_allocator::{AllocEvent, RecordingAllocator};
 pub use so_vec::SoVec;
+pub use try_reserve_error::TryReserveError;
+pub use usable_size::UsableSize;
 
 #[cfg(test)]
 mod allocator;
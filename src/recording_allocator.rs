@@ -0,0 +1,318 @@
+// Copyright 2020 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0"
+//
+// This is part of mouse-sovec
+//
+//  mouse-sovec is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sovec is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sovec.  If not, see <http://www.gnu.org/licenses/>.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt;
+use std::alloc::System;
+use std::sync::Mutex;
+
+/// An event which `RecordingAllocator` records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocEvent {
+    /// `alloc` succeeded to allocate `size` bytes.
+    Alloc {
+        /// The allocated byte size.
+        size: usize,
+    },
+    /// `realloc` succeeded to resize the memory from `old_size` bytes to `new_size` bytes.
+    Realloc {
+        /// The byte size before `realloc` .
+        old_size: usize,
+        /// The byte size after `realloc` .
+        new_size: usize,
+    },
+    /// `dealloc` deallocated `size` bytes.
+    Dealloc {
+        /// The deallocated byte size.
+        size: usize,
+    },
+}
+
+/// Wrapper of `std::alloc::System` , which records every `alloc` , `realloc` , and `dealloc` .
+///
+/// It helps to write assertions about the allocation behavior of `SoVec` .
+///
+/// `&RecordingAllocator` implements `GlobalAlloc` as well, so that the events can be inspected
+/// after passing it to `SoVec` .
+///
+/// The events are stored in a buffer of fixed size `RecordingAllocator::CAPACITY` , so recording
+/// never allocates. The events after the buffer is full are not stored, while they are still
+/// counted by `growth_count` .
+///
+/// Do not use it as `#[global_allocator]` ; then every allocation of the program is serialized
+/// by a lock, and the events of `SoVec` are mixed with those of others.
+///
+/// This is available only if feature `recording-allocator` is enabled.
+pub struct RecordingAllocator {
+    events: Mutex<Events>,
+}
+
+/// The recorded events and the number of the heap growths.
+struct Events {
+    buffer: [AllocEvent; RecordingAllocator::CAPACITY],
+    len: usize,
+    growth_count: usize,
+    // The last event and the address of the memory.
+    last: Option<(AllocEvent, usize)>,
+}
+
+impl RecordingAllocator {
+    /// The max number of the events stored.
+    pub const CAPACITY: usize = 256;
+
+    /// Creates a new instance.
+    pub const fn new() -> Self {
+        Self {
+            events: Mutex::new(Events {
+                buffer: [AllocEvent::Dealloc { size: 0 }; Self::CAPACITY],
+                len: 0,
+                growth_count: 0,
+                last: None,
+            }),
+        }
+    }
+
+    /// Returns the events recorded so far in order. (The events after the first `CAPACITY` ones
+    /// are not included.)
+    pub fn events(&self) -> Vec<AllocEvent> {
+        // Copy the events and release the lock before allocating the `Vec` .
+        let (buffer, len) = {
+            let events = self.events.lock().unwrap();
+            (events.buffer, events.len)
+        };
+        buffer[..len].to_vec()
+    }
+
+    /// Returns the number of the heap growths; i.e. the number of `alloc` and that of `realloc`
+    /// which enlarges the memory.
+    ///
+    /// `SoVec` reallocates by `alloc` , copy, and `dealloc` if feature `zeroize` is enabled. So
+    /// `alloc` immediately followed by `dealloc` of another memory of the same or the larger size
+    /// is regarded as a reallocation which does not enlarge the memory, and it is not counted.
+    pub fn growth_count(&self) -> usize {
+        self.events.lock().unwrap().growth_count
+    }
+
+    /// Forgets the all recorded events.
+    pub fn clear(&self) {
+        let mut events = self.events.lock().unwrap();
+        events.len = 0;
+        events.growth_count = 0;
+        events.last = None;
+    }
+
+    fn record(&self, event: AllocEvent, ptr: *mut u8) {
+        let ptr = ptr as usize;
+        let mut events = self.events.lock().unwrap();
+
+        match (events.last, event) {
+            (_, AllocEvent::Alloc { .. }) => events.growth_count += 1,
+            (_, AllocEvent::Realloc { old_size, new_size }) if old_size < new_size => {
+                events.growth_count += 1
+            }
+            (
+                Some((AllocEvent::Alloc { size: new_size }, new_ptr)),
+                AllocEvent::Dealloc { size: old_size },
+            ) if new_ptr != ptr && new_size <= old_size => events.growth_count -= 1,
+            _ => {}
+        }
+        events.last = Some((event, ptr));
+
+        if events.len < Self::CAPACITY {
+            let len = events.len;
+            events.buffer[len] = event;
+            events.len += 1;
+        }
+    }
+}
+
+impl Default for RecordingAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for RecordingAllocator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RecordingAllocator")
+            .field("events", &self.events())
+            .field("growth_count", &self.growth_count())
+            .finish()
+    }
+}
+
+unsafe impl GlobalAlloc for RecordingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            self.record(
+                AllocEvent::Alloc {
+                    size: layout.size(),
+                },
+                ptr,
+            );
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        self.record(
+            AllocEvent::Dealloc {
+                size: layout.size(),
+            },
+            ptr,
+        );
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            self.record(
+                AllocEvent::Realloc {
+                    old_size: layout.size(),
+                    new_size,
+                },
+                new_ptr,
+            );
+        }
+        new_ptr
+    }
+}
+
+unsafe impl GlobalAlloc for &RecordingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        (*self).alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        (*self).dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        (*self).realloc(ptr, layout, new_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SoVec;
+
+    #[test]
+    fn spill() {
+        let alloc = RecordingAllocator::new();
+        let mut v = SoVec::<u8, &RecordingAllocator>::from(&alloc);
+
+        v.extend(0..=v.capacity() as u8);
+
+        assert_eq!(1, alloc.growth_count());
+        assert!(matches!(alloc.events()[..], [AllocEvent::Alloc { .. }]));
+
+        drop(v);
+        assert_eq!(1, alloc.growth_count());
+        assert!(matches!(
+            alloc.events()[..],
+            [AllocEvent::Alloc { size: a }, AllocEvent::Dealloc { size: d }] if a == d
+        ));
+    }
+
+    // Feature `zeroize` reallocates by `alloc` and `dealloc` instead of `realloc` .
+    #[cfg(not(feature = "zeroize"))]
+    #[test]
+    fn realloc() {
+        let alloc = RecordingAllocator::new();
+        let mut v = SoVec::<u8, &RecordingAllocator>::with_capacity(100, &alloc);
+        v.reserve_exact(200);
+        v.extend(0..150);
+        v.shrink_to_fit();
+
+        assert_eq!(
+            vec![
+                AllocEvent::Alloc { size: 100 },
+                AllocEvent::Realloc {
+                    old_size: 100,
+                    new_size: 200
+                },
+                AllocEvent::Realloc {
+                    old_size: 200,
+                    new_size: 150
+                },
+            ],
+            alloc.events()
+        );
+        assert_eq!(2, alloc.growth_count());
+
+        alloc.clear();
+        assert!(alloc.events().is_empty());
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn realloc_zeroize() {
+        let alloc = RecordingAllocator::new();
+        let mut v = SoVec::<u8, &RecordingAllocator>::with_capacity(100, &alloc);
+        v.reserve_exact(100);
+        v.extend(0..150);
+        v.shrink_to_fit();
+
+        assert_eq!(
+            vec![
+                AllocEvent::Alloc { size: 100 },
+                AllocEvent::Alloc { size: 200 },
+                AllocEvent::Dealloc { size: 100 },
+                AllocEvent::Alloc { size: 150 },
+                AllocEvent::Dealloc { size: 200 },
+            ],
+            alloc.events()
+        );
+
+        // Shrinking is not a growth.
+        assert_eq!(2, alloc.growth_count());
+    }
+
+    #[test]
+    fn overflow() {
+        let alloc = RecordingAllocator::new();
+        let mut vs: Vec<SoVec<u8, &RecordingAllocator>> = Vec::new();
+        for _ in 0..(RecordingAllocator::CAPACITY + 10) {
+            vs.push(SoVec::with_capacity(100, &alloc));
+        }
+
+        // The events after the buffer is full are counted but not stored.
+        assert_eq!(RecordingAllocator::CAPACITY, alloc.events().len());
+        assert_eq!(RecordingAllocator::CAPACITY + 10, alloc.growth_count());
+
+        alloc.clear();
+        assert!(alloc.events().is_empty());
+        assert_eq!(0, alloc.growth_count());
+    }
+}
@@ -29,11 +29,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::global::Global;
 use crate::heap_buffer::HeapBuffer;
+use crate::heap_handle::HeapHandle;
+use crate::into_iter::IntoIter;
 use crate::stack_buffer::StackBuffer;
+use crate::try_reserve_error::TryReserveError;
+use crate::usable_size::UsableSize;
 use core::alloc::GlobalAlloc;
 use core::convert::{AsMut, AsRef};
-use core::mem::MaybeUninit;
+use core::fmt;
+use core::hash::Hash;
+use core::iter::FromIterator;
+use core::mem::{ManuallyDrop, MaybeUninit};
+use core::ops::{Bound, Deref, DerefMut, Range, RangeBounds};
+use std::alloc::System;
+use std::collections::HashSet;
 
 /// `SoVec` stands for `Small optimized Vector` .
 ///
@@ -51,8 +62,47 @@ where
 {
     buffer: StackBuffer<T>,
     alloc: A,
+    #[cfg(feature = "max-capacity")]
+    max_capacity: usize,
+    #[cfg(feature = "spill-observer")]
+    spill_observer: Option<Box<dyn Fn(usize) + Send + Sync>>,
 }
 
+/// The size of the max capacity field.
+#[cfg(feature = "max-capacity")]
+const MAX_CAPACITY_SIZE: usize = core::mem::size_of::<usize>();
+#[cfg(not(feature = "max-capacity"))]
+const MAX_CAPACITY_SIZE: usize = 0;
+
+/// The size of the spill observer field.
+#[cfg(feature = "spill-observer")]
+const SPILL_OBSERVER_SIZE: usize = core::mem::size_of::<Option<Box<dyn Fn(usize) + Send + Sync>>>();
+#[cfg(not(feature = "spill-observer"))]
+const SPILL_OBSERVER_SIZE: usize = 0;
+
+/// Returns the size `SoVec<T, A>` is expected to be if `A` is zero sized type; i.e. the size of
+/// `HeapBuffer` , the discriminant (the length of `StackBuffer` ,) and `max_capacity` and the
+/// spill observer if enabled.
+///
+/// Adding a field to `SoVec` bloats every instance; update this function consciously then.
+const fn expected_size<T>() -> usize {
+    core::mem::size_of::<HeapBuffer<T>>()
+        + core::mem::size_of::<usize>()
+        + MAX_CAPACITY_SIZE
+        + SPILL_OBSERVER_SIZE
+}
+
+const _: () = assert!(core::mem::size_of::<SoVec<u8, System>>() <= expected_size::<u8>());
+const _: () = assert!(core::mem::size_of::<SoVec<usize, System>>() <= expected_size::<usize>());
+const _: () =
+    assert!(core::mem::size_of::<SoVec<[u64; 32], System>>() <= expected_size::<[u64; 32]>());
+
+// Features must not change whether `SoVec` is `Send` or `Sync` .
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<SoVec<u8, System>>();
+};
+
 impl<T, A> SoVec<T, A>
 where
     A: GlobalAlloc,
@@ -63,16 +113,208 @@ where
     }
 
     /// Creates a new empty instance whose capacity is greater than or equals to `capacity` .
+    ///
+    /// If `capacity` is less than or equals to the capacity of a new instance
+    /// (i.e. `SoVec::new(alloc).capacity()` ,) this method never allocates heap memory.
     pub fn with_capacity(capacity: usize, alloc: A) -> Self {
+        Self::try_with_capacity(capacity, alloc).unwrap_or_else(|e| e.handle())
+    }
+
+    /// Creates a new empty instance whose capacity is greater than or equals to `capacity` as
+    /// `with_capacity` does, or returns an error if the capacity overflows or the allocator fails.
+    pub fn try_with_capacity(capacity: usize, alloc: A) -> Result<Self, TryReserveError> {
         let mut ret = Self::from(alloc);
 
         if StackBuffer::<T>::capacity() < capacity {
             unsafe {
-                let heap_buffer = HeapBuffer::<T>::with_capacity(capacity, &ret.alloc);
+                // Zero sized type never allocates, so it reserves as much as possible at once.
+                let capacity = if core::mem::size_of::<T>() == 0 {
+                    usize::MAX
+                } else {
+                    capacity
+                };
+                let heap_buffer = HeapBuffer::<T>::try_with_capacity(capacity, &ret.alloc)?;
                 ret.to_heap(heap_buffer);
             }
         }
 
+        debug_assert_eq!(
+            capacity <= StackBuffer::<T>::capacity(),
+            ret.is_using_stack()
+        );
+        Ok(ret)
+    }
+
+    /// Creates a new instance using `alloc` and appends the elements of `iter` .
+    ///
+    /// This is the counterpart of `collect` for an allocator which does not implement `Default` .
+    pub fn from_iter_in<I>(iter: I, alloc: A) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let mut ret = Self::from(alloc);
+        ret.extend(iter);
+        ret
+    }
+
+    /// Creates a new instance using `alloc` and appends the elements of `iter` as `from_iter_in`
+    /// does, except for that the length is read from `ExactSizeIterator::len` in advance.
+    ///
+    /// The memory is allocated at most once and the elements are written without checking the
+    /// capacity for each. (Even if `iter` yields more elements than its `len` , they are appended
+    /// safely.)
+    pub fn from_exact_iter<I>(iter: I, alloc: A) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let mut iter = iter.into_iter();
+        let mut ret = Self::with_capacity(iter.len(), alloc);
+        ret.extend_within_capacity(&mut iter);
+        ret.extend(iter);
+        ret
+    }
+
+    /// Creates a new instance taking the ownership of the heap memory of `boxed` without copying
+    /// the elements.
+    ///
+    /// The heap memory is deallocated by `alloc` afterward.
+    ///
+    /// If `boxed` is empty or `T` is zero sized type, `boxed` does not own any heap memory; then
+    /// the elements are moved into a new instance instead.
+    ///
+    /// # Safety
+    ///
+    /// `boxed` must have been allocated by `alloc` or an allocator compatible with it.
+    /// (e.g. if the global allocator is `std::alloc::System` , `alloc` can be `System` .)
+    pub unsafe fn from_box(boxed: Box<[T]>, alloc: A) -> Self {
+        let len = boxed.len();
+        let ptr = Box::into_raw(boxed) as *mut T;
+
+        if len == 0 || core::mem::size_of::<T>() == 0 {
+            let mut ret = Self::with_capacity(len, alloc);
+            core::ptr::copy_nonoverlapping(ptr, ret.as_mut_ptr(), len);
+            ret.set_len(len);
+            return ret;
+        }
+
+        let mut ret = Self::from(alloc);
+        ret.to_heap(HeapBuffer::from_raw_parts(ptr, len, len));
+        ret
+    }
+
+    /// Creates a new empty instance whose capacity is greater than or equals to `capacity` as
+    /// `with_capacity` does.
+    ///
+    /// If the heap is allocated, the capacity is as many as the allocated memory can hold
+    /// according to `alloc.usable_size` ; i.e. the extra bytes the allocator rounds up to are
+    /// not wasted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new capacity overflows.
+    pub fn with_capacity_usable(capacity: usize, alloc: A) -> Self
+    where
+        A: UsableSize,
+    {
+        if capacity <= StackBuffer::<T>::capacity() || core::mem::size_of::<T>() == 0 {
+            return Self::with_capacity(capacity, alloc);
+        }
+
+        let mut ret = Self::from(alloc);
+        unsafe {
+            let heap_buffer = HeapBuffer::<T>::try_with_usable_capacity(capacity, &ret.alloc)
+                .unwrap_or_else(|e| e.handle());
+            ret.to_heap(heap_buffer);
+        }
+        ret
+    }
+
+    /// Creates a new instance taking the heap memory of `vec` without moving the elements.
+    ///
+    /// The heap memory is deallocated by `alloc` afterward. `self` keeps using the heap even if
+    /// the elements fit in the stack buffer; see also `from_vec_compacting` .
+    ///
+    /// This is the unsafe variant of `From<Vec<T>>` for `SoVec<T, Global>` to use another
+    /// allocator than `Global` .
+    ///
+    /// If `vec` does not own any heap memory (i.e. the capacity is 0 or `T` is zero sized type,)
+    /// the elements are moved into a new instance instead.
+    ///
+    /// # Safety
+    ///
+    /// `vec` must have been allocated by `alloc` or an allocator compatible with it.
+    /// (e.g. if the global allocator is `std::alloc::System` , `alloc` can be `System` .)
+    pub unsafe fn from_vec(vec: Vec<T>, alloc: A) -> Self {
+        let mut vec = ManuallyDrop::new(vec);
+        let ptr = vec.as_mut_ptr();
+        let len = vec.len();
+        let capacity = vec.capacity();
+
+        if capacity == 0 || core::mem::size_of::<T>() == 0 {
+            let mut ret = Self::with_capacity(len, alloc);
+            core::ptr::copy_nonoverlapping(ptr, ret.as_mut_ptr(), len);
+            ret.set_len(len);
+            return ret;
+        }
+
+        let mut ret = Self::from(alloc);
+        ret.to_heap(HeapBuffer::from_raw_parts(ptr, len, capacity));
+        ret
+    }
+
+    /// Creates a new instance from `vec` as `from_vec` does, except for moving the elements into
+    /// the stack buffer if they fit there.
+    ///
+    /// Then the heap memory of `vec` is deallocated by the global allocator; i.e. this method
+    /// costs moving the elements to regain the small optimization.
+    ///
+    /// # Safety
+    ///
+    /// `vec` must have been allocated by `alloc` or an allocator compatible with it unless the
+    /// elements fit in the stack buffer.
+    pub unsafe fn from_vec_compacting(mut vec: Vec<T>, alloc: A) -> Self {
+        let len = vec.len();
+        if StackBuffer::<T>::capacity() < len {
+            return Self::from_vec(vec, alloc);
+        }
+
+        let mut ret = Self::from(alloc);
+        core::ptr::copy_nonoverlapping(vec.as_ptr(), ret.as_mut_ptr(), len);
+        vec.set_len(0);
+        ret.set_len(len);
+        ret
+    }
+
+    /// Creates a new instance reusing the heap memory and the elements `handle` points to.
+    ///
+    /// This is the inverse of `dismantle` ; the max capacity of the dismantled instance is
+    /// restored as well. (The spill observer is not.) If `dismantle` returned no handle, create a
+    /// new instance by `SoVec::from(alloc)` instead.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must have been created by `dismantle` of `SoVec<T, B>` , and the memory must be
+    /// able to be deallocated by `alloc` . (e.g. `alloc` is the allocator `dismantle` returned.)
+    pub unsafe fn from_heap_handle(handle: HeapHandle<T>, alloc: A) -> Self {
+        let max_capacity = handle.max_capacity();
+
+        let mut ret = Self::from(alloc);
+        ret.to_heap(handle.into_buffer());
+        ret.set_max_capacity_unchecked(max_capacity);
+        ret
+    }
+
+    /// Creates a new instance using `alloc` holding `n` elements; the `i` th element is `f(i)` .
+    ///
+    /// This method does not allocate heap memory if `n` elements fit in the stack buffer.
+    /// If `f` panics, the elements created before are dropped.
+    pub fn from_fn<F>(n: usize, f: F, alloc: A) -> Self
+    where
+        F: FnMut(usize) -> T,
+    {
+        let mut ret = Self::with_capacity(n, alloc);
+        ret.fill_spare(n, f);
         ret
     }
 
@@ -105,48 +347,221 @@ where
         }
     }
 
+    /// Returns true if `self` has moved the elements to the heap, or false.
+    ///
+    /// Once `self` has spilled, it keeps using the heap until `shrink_to_fit` is called.
+    /// (`truncate` , `clear` , and so on never reallocate.)
+    pub fn spilled(&self) -> bool {
+        !self.is_using_stack()
+    }
+
     /// Returns the number of the elements `self` can hold without allocating.
+    ///
+    /// It is `max_capacity` at most even if the buffer is larger than that.
     pub fn capacity(&self) -> usize {
-        if self.is_using_stack() {
-            StackBuffer::<T>::capacity()
-        } else {
-            self.as_heap().capacity()
+        core::cmp::min(self.buffer_capacity(), self.max_capacity())
+    }
+
+    /// Sets `f` to be called with the new capacity whenever `self` moves the elements from the
+    /// stack buffer to the heap.
+    ///
+    /// It is not called when the heap is reallocated. It helps to find where `SoVec` spills.
+    ///
+    /// The observer is not inherited by `clone` .
+    ///
+    /// `f` must be `Send` and `Sync` so that enabling the feature does not make `SoVec` lose
+    /// `Send` nor `Sync` ; otherwise, another crate depending on this crate could fail to build
+    /// because cargo unifies the features.
+    ///
+    /// This is available only if feature `spill-observer` is enabled.
+    #[cfg(feature = "spill-observer")]
+    pub fn set_spill_observer<F>(&mut self, f: F)
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.spill_observer = Some(Box::new(f));
+    }
+
+    /// Returns the max capacity set by `set_max_capacity` .
+    ///
+    /// The default is `usize::MAX` . It is always `usize::MAX` unless feature `max-capacity` is
+    /// enabled.
+    pub fn max_capacity(&self) -> usize {
+        #[cfg(feature = "max-capacity")]
+        return self.max_capacity;
+        #[cfg(not(feature = "max-capacity"))]
+        return usize::MAX;
+    }
+
+    /// Limits the capacity to `max_capacity` ; i.e. `self` refuses to hold more than
+    /// `max_capacity` elements after that.
+    ///
+    /// The methods reserving more capacity (e.g. `reserve` , `push_grow` , `insert` ) panic, and
+    /// the fallible ones (e.g. `try_reserve` , `push_checked` ) return an error instead.
+    ///
+    /// This method does not reallocate even if the current buffer is larger than `max_capacity` .
+    ///
+    /// The instances derived from `self` (e.g. by `clone` , `split_off` , `take_all` , or
+    /// `reallocate_in` ) inherit `max_capacity` .
+    ///
+    /// This is available only if feature `max-capacity` is enabled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_capacity` is less than `self.len()` .
+    #[cfg(feature = "max-capacity")]
+    pub fn set_max_capacity(&mut self, max_capacity: usize) {
+        let len = self.len();
+        if max_capacity < len {
+            panic!(
+                "max_capacity (is {}) should be >= len (is {})",
+                max_capacity, len
+            );
+        }
+
+        self.max_capacity = max_capacity;
+    }
+
+    /// Reserves capacity for at least `additional` more elements.
+    ///
+    /// Unlike `reserve_exact` , this method may reserve more space to avoid frequent reallocations.
+    /// (The capacity grows geometrically.)
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new capacity overflows.
+    pub fn reserve(&mut self, additional: usize) {
+        self.try_reserve(additional).unwrap_or_else(|e| e.handle());
+    }
+
+    /// Reserves capacity for at least `additional` more elements, rounding the new capacity up to
+    /// a power of two.
+    ///
+    /// This is useful for the code masking indices with the capacity, though it may waste up to
+    /// about half of the allocated memory.
+    ///
+    /// Nothing is done if `self` already has sufficient capacity. Note that the capacity of the
+    /// stack buffer is not always a power of two; i.e. the capacity is a power of two only after
+    /// `self` has started to use the heap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new capacity overflows.
+    pub fn reserve_pow2(&mut self, additional: usize) {
+        let required = self
+            .len()
+            .checked_add(additional)
+            .unwrap_or_else(|| TryReserveError::CapacityOverflow.handle());
+
+        if required <= self.capacity() {
+            return;
         }
+
+        // The rounding stops at `max_capacity` .
+        let new_capacity = required
+            .checked_next_power_of_two()
+            .unwrap_or_else(|| TryReserveError::CapacityOverflow.handle());
+        let new_capacity =
+            core::cmp::min(new_capacity, core::cmp::max(required, self.max_capacity()));
+        self.reserve_exact(new_capacity - self.len());
     }
 
     /// Reserves the minimum capacity to insert `additional` more elements.
     ///
     /// After this method is called, `self.capacity` will return the number
     /// to be greater than or equals to `self.len() + additional` .
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new capacity overflows.
     pub fn reserve_exact(&mut self, additional: usize) {
-        let new_capacity = self.len() + additional;
+        self.try_reserve_exact(additional)
+            .unwrap_or_else(|e| e.handle());
+    }
+
+    /// Tries to reserve capacity for at least `additional` more elements as `reserve` does,
+    /// and returns an error if the capacity overflows or the allocator fails.
+    ///
+    /// `self` is not changed on error.
+    #[must_use = "the capacity may not be reserved"]
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let required = self
+            .len()
+            .checked_add(additional)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+
+        if required <= self.capacity() {
+            return Ok(());
+        }
+
+        // The geometric growth stops at `max_capacity` .
+        let new_capacity = core::cmp::max(required, self.capacity().saturating_mul(2));
+        let new_capacity =
+            core::cmp::min(new_capacity, core::cmp::max(required, self.max_capacity()));
+        self.try_reserve_exact(new_capacity - self.len())
+    }
+
+    /// Tries to reserve the minimum capacity to insert `additional` more elements as
+    /// `reserve_exact` does, and returns an error if the capacity overflows or the allocator fails.
+    ///
+    /// `self` is not changed on error.
+    #[must_use = "the capacity may not be reserved"]
+    pub fn try_reserve_exact(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let new_capacity = self
+            .len()
+            .checked_add(additional)
+            .ok_or(TryReserveError::CapacityOverflow)?;
 
         if new_capacity <= self.capacity() {
-            return;
+            return Ok(());
         }
+        let max_capacity = self.max_capacity();
+        if max_capacity < new_capacity {
+            return Err(TryReserveError::MaxCapacityExceeded { max_capacity });
+        }
+
+        // Zero sized type never allocates, so it reserves as much as possible at once.
+        let new_capacity = if core::mem::size_of::<T>() == 0 {
+            max_capacity
+        } else {
+            new_capacity
+        };
 
         unsafe {
             if self.is_using_stack() {
-                let mut heap_buffer = HeapBuffer::<T>::with_capacity(new_capacity, &self.alloc);
+                let mut heap_buffer =
+                    HeapBuffer::<T>::try_with_capacity(new_capacity, &self.alloc)?;
                 std::ptr::copy_nonoverlapping(self.as_ptr(), heap_buffer.as_mut_ptr(), self.len());
                 heap_buffer.set_len(self.len());
 
                 self.to_heap(heap_buffer);
             } else {
                 let alloc = &self.alloc as *const A;
-                self.as_mut_heap().set_capacity(new_capacity, &*alloc);
+                self.as_mut_heap().try_set_capacity(new_capacity, &*alloc)?;
             }
         }
+
+        Ok(())
     }
 
     /// Appends `elm` to the end of `self` .
     ///
     /// The caller must ensure that `self` has sufficient capacity in advance.
     ///
+    /// There are 3 methods to append an element.
+    ///
+    /// - `push` does not check the capacity at all. (It is checked only in debug mode.)
+    /// - `push_checked` checks the capacity, and returns `elm` back if `self` is full.
+    ///   It never reallocates.
+    /// - `push_grow` reserves the capacity geometrically if `self` is full.
+    ///   It behaves like `std::collections::Vec::push` .
+    ///
     /// # Safety
     ///
     /// The behavior is undefined if `self.len` is greater than or equals to
     /// `self.capacity` .
+    ///
+    /// Note that zero sized type never touches the memory; it just increments the length.
     pub unsafe fn push(&mut self, elm: T) {
         debug_assert!(self.len() < self.capacity());
 
@@ -155,9 +570,56 @@ where
         self.set_len(self.len() + 1);
     }
 
+    /// Appends `elm` to the end of `self` if `self` has sufficient capacity;
+    /// otherwise, returns `Err(elm)` .
+    ///
+    /// This method never reallocates. See also `push` .
+    #[must_use = "`elm` is returned back if `self` is full"]
+    pub fn push_checked(&mut self, elm: T) -> Result<(), T> {
+        if self.len() < self.capacity() {
+            unsafe { self.push(elm) };
+            Ok(())
+        } else {
+            Err(elm)
+        }
+    }
+
+    /// Appends `elm` to the end of `self` , reserving the capacity if necessary.
+    ///
+    /// The capacity grows geometrically, so the amortized cost is O(1). See also `push` .
+    pub fn push_grow(&mut self, elm: T) {
+        self.reserve(1);
+        unsafe { self.push(elm) };
+    }
+
+    /// Reserves the capacity for exactly `n` more elements, and appends the first `n` elements of
+    /// `iter` .
+    ///
+    /// The rest of `iter` is not consumed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `iter` yields fewer than `n` elements.
+    /// (The elements yielded before that are kept in `self` .)
+    pub fn push_n<I>(&mut self, iter: I, n: usize)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        self.reserve_exact(n);
+
+        let mut iter = iter.into_iter();
+        for i in 0..n {
+            match iter.next() {
+                Some(elm) => unsafe { self.push(elm) },
+                None => panic!("iter yielded only {} elements (n is {})", i, n),
+            }
+        }
+    }
+
     /// Removes the last element and returns it if any.
+    #[must_use = "use `truncate` to just drop the last element"]
     pub fn pop(&mut self) -> Option<T> {
-        if self.len() == 0 {
+        if self.is_empty() {
             None
         } else {
             unsafe {
@@ -169,6 +631,73 @@ where
         }
     }
 
+    /// Inserts `elm` at position `index` , shifting all the elements after it to the right.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than `self.len()` .
+    pub fn insert(&mut self, index: usize, elm: T) {
+        let len = self.len();
+        if len < index {
+            panic!(
+                "insertion index (is {}) should be <= len (is {})",
+                index, len
+            );
+        }
+
+        self.reserve(1);
+
+        unsafe {
+            let ptr = self.as_mut_ptr().add(index);
+            core::ptr::copy(ptr, ptr.add(1), len - index);
+            core::ptr::write(ptr, elm);
+            self.set_len(len + 1);
+        }
+    }
+
+    /// Removes and returns the element at position `index` , shifting all the elements after it
+    /// to the left.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    #[must_use]
+    pub fn remove(&mut self, index: usize) -> T {
+        let len = self.len();
+        if len <= index {
+            panic!("removal index (is {}) should be < len (is {})", index, len);
+        }
+
+        unsafe {
+            let ptr = self.as_mut_ptr().add(index);
+            let ret = core::ptr::read(ptr);
+            core::ptr::copy(ptr.add(1), ptr, len - index - 1);
+            self.set_len(len - 1);
+            ret
+        }
+    }
+
+    /// Inserts `elm` at the front of `self` , shifting all the elements to the right.
+    ///
+    /// This is O(n) because all the elements are moved. Consider `std::collections::VecDeque`
+    /// if the front operations are frequent.
+    pub fn push_front(&mut self, elm: T) {
+        self.insert(0, elm);
+    }
+
+    /// Removes the first element and returns it, or `None` if `self` is empty.
+    ///
+    /// This is O(n) because all the rest elements are moved. Consider
+    /// `std::collections::VecDeque` if the front operations are frequent.
+    #[must_use]
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.remove(0))
+        }
+    }
+
     /// Returns a raw pointer to the buffer of `self` .
     ///
     /// # Warnings
@@ -213,301 +742,5354 @@ where
         }
     }
 
-    /// Removes the all elements keeping the allocated capacity, and set the length 0.
+    /// Returns the 2 raw pointers spanning the buffer of `self` .
     ///
-    /// Note this has the same effect to `self.truncate(0)` .
-    pub fn clear(&mut self) {
-        self.truncate(0);
+    /// The returned range is half-open; i.e. the end pointer points one past the last element.
+    ///
+    /// # Warnings
+    ///
+    /// The caller must ensure that `self` outlives the pointers this function returns,
+    /// or else they will end up pointing to garbage. Modifying or moving `self` may cause
+    /// its buffer to be reallocated, which would also make any pointers to it invalid.
+    ///
+    /// # Safety
+    ///
+    /// This method itself is safe, however, moving `self` can invalidate the returned value
+    /// unlike to the same name methods of `std::collections::Vec` .
+    ///
+    /// `unsafe` modifier is added to alert it.
+    pub unsafe fn as_ptr_range(&self) -> Range<*const T> {
+        let start = self.as_ptr();
+        let end = start.add(self.len());
+        start..end
     }
 
-    /// Enshortens `self`, keeping the first `new_len` elements and dropping the rest.
+    /// Returns the 2 raw pointers spanning the buffer of `self` .
     ///
-    /// If `new_len` is greater than or equals to the current length, nothing is done.
+    /// The returned range is half-open; i.e. the end pointer points one past the last element.
     ///
-    /// Note that this method does not have any effect on the allocated capacity of `self` .
-    pub fn truncate(&mut self, new_len: usize) {
-        if self.len() <= new_len {
-            return;
-        }
-
-        unsafe {
-            for i in new_len..self.len() {
-                core::ptr::drop_in_place(self.as_mut_ptr().add(i));
-            }
-
-            self.set_len(new_len);
-        }
+    /// # Warnings
+    ///
+    /// The caller must ensure that `self` outlives the pointers this function returns,
+    /// or else they will end up pointing to garbage. Modifying or moving `self` may cause
+    /// its buffer to be reallocated, which would also make any pointers to it invalid.
+    ///
+    /// # Safety
+    ///
+    /// This method itself is safe, however, moving `self` can invalidate the returned value
+    /// unlike to the same name methods of `std::collections::Vec` .
+    ///
+    /// `unsafe` modifier is added to alert it.
+    pub unsafe fn as_mut_ptr_range(&mut self) -> Range<*mut T> {
+        let len = self.len();
+        let start = self.as_mut_ptr();
+        let end = start.add(len);
+        start..end
     }
 
-    /// Shrinks the capacitance of `self` as much as possible.
-    pub fn shrink_to_fit(&mut self) {
-        if self.is_using_stack() {
-            return;
-        } else {
-            let alloc = &self.alloc as *const A;
-            let new_capacity = self.len();
-            unsafe { self.as_mut_heap().set_capacity(new_capacity, &*alloc) };
-        }
+    /// Calls `f` with a raw pointer to the buffer of `self` and returns the result.
+    ///
+    /// Unlike `as_ptr` , this method is safe because `self` is borrowed and can't move while `f`
+    /// is running. The pointer must not be used after `f` returns.
+    pub fn with_ptr<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(*const T) -> R,
+    {
+        f(unsafe { self.as_ptr() })
     }
 
-    /// Returns true if `self` is using StackBuffer; otherwise, i.e. `self` is using `HeapBuffer`,
-    /// returns false.
-    fn is_using_stack(&self) -> bool {
-        self.buffer.is_available()
+    /// Calls `f` with a raw pointer to the buffer of `self` and returns the result.
+    ///
+    /// Unlike `as_mut_ptr` , this method is safe because `self` is borrowed and can't move while
+    /// `f` is running. The pointer must not be used after `f` returns.
+    pub fn with_mut_ptr<R, F>(&mut self, f: F) -> R
+    where
+        F: FnOnce(*mut T) -> R,
+    {
+        f(unsafe { self.as_mut_ptr() })
     }
 
-    /// Returns `self.bufer` .
-    fn as_stack(&self) -> &StackBuffer<T> {
-        debug_assert!(self.is_using_stack());
-        &self.buffer
+    /// Returns a slice of the all elements.
+    ///
+    /// Indexing `self` checks the bounds every time. To access many elements in a loop, it is
+    /// often faster to call this method once and to index (or iterate) the returned slice,
+    /// because the compiler can elide the bounds checks then.
+    pub fn as_slice(&self) -> &[T] {
+        self.as_ref()
     }
 
-    /// Returns `self.bufer` .
-    fn as_mut_stack(&mut self) -> &mut StackBuffer<T> {
-        debug_assert!(self.is_using_stack());
-        &mut self.buffer
+    /// Returns a mutable slice of the all elements.
+    ///
+    /// See also `as_slice` .
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        self.as_mut()
     }
 
-    /// Forces to regards `self.buffer` as HeapBuffer and returns it.
-    fn as_heap(&self) -> &HeapBuffer<T> {
-        debug_assert_eq!(false, self.is_using_stack());
-        let ptr = &self.buffer as *const StackBuffer<T>;
-        let ptr = ptr as *const u8;
-        let ptr = ptr as *const HeapBuffer<T>;
-        unsafe { &*ptr }
+    /// Returns a reference to the element at `index` without bounds checking.
+    ///
+    /// # Safety
+    ///
+    /// The behavior is undefined if `index` is greater than or equals to `self.len()` .
+    pub unsafe fn index_unchecked(&self, index: usize) -> &T {
+        debug_assert!(index < self.len());
+        self.as_slice().get_unchecked(index)
     }
 
-    /// Forces to regards `self.buffer` as HeapBuffer and returns it.
-    fn as_mut_heap(&mut self) -> &mut HeapBuffer<T> {
-        debug_assert_eq!(false, self.is_using_stack());
-        let ptr = &mut self.buffer as *mut StackBuffer<T>;
-        let ptr = ptr as *mut u8;
-        let ptr = ptr as *mut HeapBuffer<T>;
-        unsafe { &mut *ptr }
+    /// Returns a mutable reference to the element at `index` without bounds checking.
+    ///
+    /// # Safety
+    ///
+    /// The behavior is undefined if `index` is greater than or equals to `self.len()` .
+    pub unsafe fn index_unchecked_mut(&mut self, index: usize) -> &mut T {
+        debug_assert!(index < self.len());
+        self.as_mut_slice().get_unchecked_mut(index)
     }
 
-    /// Disables small optimization forces to overwrite `self.stack` .
+    /// Returns mutable references to the elements at `indices` at once.
     ///
-    /// Note that this method does not move each element.
-    unsafe fn to_heap(&mut self, new_buffer: HeapBuffer<T>) {
-        debug_assert!(self.is_using_stack());
+    /// Returns `None` if any index is out of bounds, or if any 2 indices are the same.
+    pub fn get_disjoint_mut<const N: usize>(&mut self, indices: [usize; N]) -> Option<[&mut T; N]> {
+        let len = self.len();
 
-        let ptr = &mut self.buffer as *mut StackBuffer<T>;
-        let ptr = ptr as *mut u8;
-        let ptr = ptr as *mut HeapBuffer<T>;
+        for (i, index) in indices.iter().enumerate() {
+            if len <= *index || indices[..i].contains(index) {
+                return None;
+            }
+        }
 
-        core::ptr::write(ptr, new_buffer);
-        self.as_mut_stack().disable();
+        // Each index is in bounds and different from the others;
+        // i.e. the returned references never alias.
+        unsafe {
+            let ptr = self.as_mut_ptr();
+            Some(indices.map(|i| &mut *ptr.add(i)))
+        }
     }
-}
 
-impl<T, A> From<A> for SoVec<T, A>
-where
-    A: GlobalAlloc,
-{
-    fn from(alloc: A) -> Self {
-        Self {
-            buffer: StackBuffer::<T>::new(),
-            alloc,
+    /// Returns a reference to the element at `index` , or `default` if `index` is out of bounds.
+    pub fn get_or<'a>(&'a self, index: usize, default: &'a T) -> &'a T {
+        self.as_ref().get(index).unwrap_or(default)
+    }
+
+    /// Calls `f` with a mutable reference to the element at `index` and returns the result, or
+    /// returns `None` without calling `f` if `index` is out of bounds.
+    pub fn modify<R, F>(&mut self, index: usize, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        self.as_mut_slice().get_mut(index).map(f)
+    }
+
+    /// Replaces the element at `index` with `elm` and returns the old one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    #[must_use]
+    pub fn replace(&mut self, index: usize, elm: T) -> T {
+        let len = self.len();
+        if len <= index {
+            panic!(
+                "replacement index (is {}) should be < len (is {})",
+                index, len
+            );
         }
+
+        core::mem::replace(&mut self.as_mut_slice()[index], elm)
     }
-}
 
-impl<T, A> Default for SoVec<T, A>
-where
-    A: GlobalAlloc + Default,
-{
-    fn default() -> Self {
-        Self::from(A::default())
+    /// Returns a mutable reference to the element at `index` , appending elements created by `f`
+    /// until `index` is in bounds if necessary.
+    pub fn ensure_len_with<F>(&mut self, index: usize, mut f: F) -> &mut T
+    where
+        F: FnMut() -> T,
+    {
+        let len = self.len();
+        if len <= index {
+            self.extend((len..=index).map(|_| f()));
+        }
+
+        &mut self.as_mut_slice()[index]
     }
-}
 
-impl<T, A> AsRef<[T]> for SoVec<T, A>
-where
-    A: GlobalAlloc,
-{
-    fn as_ref(&self) -> &[T] {
-        unsafe { core::slice::from_raw_parts(self.as_ptr(), self.len()) }
+    /// Returns the index of the first element which `pred` returns true for, or `None` .
+    pub fn position<P>(&self, pred: P) -> Option<usize>
+    where
+        P: FnMut(&T) -> bool,
+    {
+        self.as_ref().iter().position(pred)
+    }
+
+    /// Returns the index of the last element which `pred` returns true for, or `None` .
+    pub fn rposition<P>(&self, pred: P) -> Option<usize>
+    where
+        P: FnMut(&T) -> bool,
+    {
+        self.as_ref().iter().rposition(pred)
+    }
+
+    /// Returns a reference to the first element which `pred` returns true for, or `None` .
+    pub fn find<P>(&self, mut pred: P) -> Option<&T>
+    where
+        P: FnMut(&T) -> bool,
+    {
+        self.as_ref().iter().find(|elm| pred(elm))
+    }
+
+    /// Returns an iterator over the subslices separated by the elements which `pred` returns true
+    /// for.
+    pub fn split<F>(&self, pred: F) -> core::slice::Split<'_, T, F>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.as_ref().split(pred)
+    }
+
+    /// Returns an iterator over the subslices separated by the elements which `pred` returns true
+    /// for, starting from the back.
+    pub fn rsplit<F>(&self, pred: F) -> core::slice::RSplit<'_, T, F>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.as_ref().rsplit(pred)
+    }
+
+    /// Returns an iterator over `chunk_size` elements at a time.
+    ///
+    /// The last up to `chunk_size - 1` elements are omitted, and they can be retrieved by
+    /// `remainder` method of the iterator.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is 0.
+    pub fn chunks_exact(&self, chunk_size: usize) -> core::slice::ChunksExact<'_, T> {
+        self.as_ref().chunks_exact(chunk_size)
+    }
+
+    /// Returns an iterator over `chunk_size` elements at a time as mutable slices.
+    ///
+    /// The last up to `chunk_size - 1` elements are omitted, and they can be retrieved by
+    /// `into_remainder` method of the iterator.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is 0.
+    pub fn chunks_exact_mut(&mut self, chunk_size: usize) -> core::slice::ChunksExactMut<'_, T> {
+        self.as_mut().chunks_exact_mut(chunk_size)
+    }
+
+    /// Splits the elements into `n` disjoint mutable slices, whose lengths differ by 1 at most.
+    ///
+    /// The slices are yielded in order, and the longer ones come first. Some of them are empty if
+    /// `self.len()` is less than `n` . This is useful to hand each slice to a thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is 0.
+    pub fn par_chunks_mut(&mut self, n: usize) -> impl Iterator<Item = &mut [T]> + '_ {
+        if n == 0 {
+            panic!("the number of the chunks should be > 0");
+        }
+
+        let len = self.len();
+        let (size, longer) = (len / n, len % n);
+        let mut rest = self.as_mut_slice();
+
+        (0..n).map(move |i| {
+            let mid = if i < longer { size + 1 } else { size };
+            let (head, tail) = core::mem::take(&mut rest).split_at_mut(mid);
+            rest = tail;
+            head
+        })
+    }
+
+    /// Splits `self` at the first element equal to `needle` , and returns the elements before
+    /// and after it, or `None` if no element equals to `needle` .
+    ///
+    /// The delimiter itself is included in neither. This is like `str::split_once` .
+    pub fn split_once(&self, needle: &T) -> Option<(&[T], &[T])>
+    where
+        T: PartialEq,
+    {
+        let slice = self.as_ref();
+        let index = slice.iter().position(|elm| elm == needle)?;
+        Some((&slice[..index], &slice[index + 1..]))
+    }
+
+    /// Returns true if `needle` is a prefix of `self` , or false.
+    pub fn starts_with(&self, needle: &[T]) -> bool
+    where
+        T: PartialEq,
+    {
+        self.as_ref().starts_with(needle)
+    }
+
+    /// Returns true if `needle` is a suffix of `self` , or false.
+    pub fn ends_with(&self, needle: &[T]) -> bool
+    where
+        T: PartialEq,
+    {
+        self.as_ref().ends_with(needle)
+    }
+
+    /// Returns true if the elements are sorted in ascending order, or false.
+    pub fn is_sorted(&self) -> bool
+    where
+        T: PartialOrd,
+    {
+        self.as_ref().is_sorted()
+    }
+
+    /// Returns true if `compare` returns true for every 2 adjacent elements, or false.
+    ///
+    /// `compare` is called as `compare(&self[i], &self[i + 1])` .
+    pub fn is_sorted_by<F>(&self, compare: F) -> bool
+    where
+        F: FnMut(&T, &T) -> bool,
+    {
+        self.as_ref().is_sorted_by(compare)
+    }
+
+    /// Returns the range of the elements equal to `x` assuming `self` is sorted.
+    ///
+    /// If no element equals to `x` , returns the empty range at the position where `x` could be
+    /// inserted keeping the order. The result is meaningless unless `self` is sorted.
+    ///
+    /// This method performs binary search twice; i.e. it is O(log n).
+    pub fn equal_range(&self, x: &T) -> Range<usize>
+    where
+        T: Ord,
+    {
+        let slice = self.as_ref();
+        let start = slice.partition_point(|elm| elm < x);
+        let end = start + slice[start..].partition_point(|elm| elm <= x);
+        start..end
+    }
+
+    /// Returns a reference to the maximum element, or `None` if `self` is empty.
+    ///
+    /// If several elements are equally maximum, the last one is returned.
+    pub fn max(&self) -> Option<&T>
+    where
+        T: Ord,
+    {
+        self.as_ref().iter().max()
+    }
+
+    /// Returns a reference to the minimum element, or `None` if `self` is empty.
+    ///
+    /// If several elements are equally minimum, the first one is returned.
+    pub fn min(&self) -> Option<&T>
+    where
+        T: Ord,
+    {
+        self.as_ref().iter().min()
+    }
+
+    /// Returns a mutable reference to the first element and the rest, or `None` if `self` is
+    /// empty.
+    pub fn split_first_mut(&mut self) -> Option<(&mut T, &mut [T])> {
+        self.as_mut().split_first_mut()
+    }
+
+    /// Returns a mutable reference to the last element and the rest, or `None` if `self` is
+    /// empty.
+    pub fn split_last_mut(&mut self) -> Option<(&mut T, &mut [T])> {
+        self.as_mut().split_last_mut()
+    }
+
+    /// Removes the all elements keeping the allocated capacity, and set the length 0.
+    ///
+    /// Note this has the same effect to `self.truncate(0)` ; i.e. this is O(1) if `T` does not
+    /// need drop.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Appends the all elements of `iter` as `extend` does, and returns the number of the appended
+    /// elements.
+    pub fn append_iter<I>(&mut self, iter: I) -> usize
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let len = self.len();
+        self.extend(iter);
+        self.len() - len
+    }
+
+    /// Moves the all elements of each vector in `others` to the end of `self` in order.
+    ///
+    /// The elements are moved (not cloned,) and each buffer of `others` is deallocated just after
+    /// its elements are moved. The capacity is reserved geometrically for each vector.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new capacity overflows.
+    pub fn extend_move<B, I>(&mut self, others: I)
+    where
+        B: GlobalAlloc,
+        I: IntoIterator<Item = SoVec<T, B>>,
+    {
+        for mut other in others {
+            let len = other.len();
+            self.reserve(len);
+
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    other.as_ptr(),
+                    self.as_mut_ptr().add(self.len()),
+                    len,
+                );
+                other.set_len(0);
+                self.set_len(self.len() + len);
+            }
+        }
+    }
+
+    /// Appends the all elements of `slices` in order.
+    ///
+    /// The total length is reserved at once before copying.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the total length overflows `usize` .
+    pub fn extend_from_slices(&mut self, slices: &[&[T]])
+    where
+        T: Copy,
+    {
+        let additional = slices
+            .iter()
+            .try_fold(0_usize, |acc, slice| acc.checked_add(slice.len()))
+            .expect("capacity overflow");
+        self.reserve(additional);
+
+        for slice in slices {
+            unsafe { self.extend_from_slice_unchecked(slice) };
+        }
+    }
+
+    /// Appends the all elements of `src` without checking the capacity.
+    ///
+    /// This is the bulk version of `push` .
+    ///
+    /// # Safety
+    ///
+    /// The behavior is undefined if `self.len() + src.len()` is greater than `self.capacity()` .
+    pub unsafe fn extend_from_slice_unchecked(&mut self, src: &[T])
+    where
+        T: Copy,
+    {
+        debug_assert!(src.len() <= self.capacity() - self.len());
+
+        let len = self.len();
+        let ptr = self.as_mut_ptr().add(len);
+        core::ptr::copy_nonoverlapping(src.as_ptr(), ptr, src.len());
+        self.set_len(len + src.len());
+    }
+
+    /// Swaps the all elements with those of `other` .
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `other` differs from that of `self` .
+    pub fn swap_with_slice(&mut self, other: &mut [T]) {
+        self.as_mut_slice().swap_with_slice(other);
+    }
+
+    /// Copies the all elements into the front of `dst` at once.
+    ///
+    /// The elements of `dst` after `self.len()` are not changed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dst` is shorter than `self` .
+    pub fn copy_to_slice(&self, dst: &mut [T])
+    where
+        T: Copy,
+    {
+        let len = self.len();
+        if dst.len() < len {
+            panic!("dst.len() (is {}) should be >= len (is {})", dst.len(), len);
+        }
+
+        unsafe { core::ptr::copy_nonoverlapping(self.as_ptr(), dst.as_mut_ptr(), len) };
+    }
+
+    /// Resizes `self` so that the length equals to `new_len` .
+    ///
+    /// If `new_len` is greater than the current length, `self` is extended by clones of `value` ;
+    /// otherwise, `self` is truncated.
+    ///
+    /// Note that this method does not shrink the capacity as well as `truncate` .
+    pub fn resize(&mut self, new_len: usize, value: T)
+    where
+        T: Clone,
+    {
+        self.try_resize(new_len, value)
+            .unwrap_or_else(|e| e.handle());
+    }
+
+    /// Tries to resize `self` as `resize` does, and returns an error if the capacity overflows
+    /// or the allocator fails.
+    ///
+    /// `self` is not changed on error.
+    /// If `T::clone` panics, the elements cloned before are kept and `self` is still valid.
+    #[must_use = "`self` may not be resized"]
+    pub fn try_resize(&mut self, new_len: usize, value: T) -> Result<(), TryReserveError>
+    where
+        T: Clone,
+    {
+        let len = self.len();
+        if new_len <= len {
+            self.truncate(new_len);
+            return Ok(());
+        }
+
+        self.try_reserve(new_len - len)?;
+
+        unsafe {
+            let ptr = self.as_mut_ptr();
+            let mut guard = SetLenOnDrop::new(self);
+
+            while guard.len + 1 < new_len {
+                core::ptr::write(ptr.add(guard.len), value.clone());
+                guard.len += 1;
+            }
+
+            core::ptr::write(ptr.add(guard.len), value);
+            guard.len += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the spare capacity as a slice of `MaybeUninit<T>` .
+    ///
+    /// After writing elements to the returned slice, call `set_len` to make them a part of `self` .
+    /// (`fill_spare` does the both safely.)
+    pub fn spare_capacity_mut(&mut self) -> &mut [MaybeUninit<T>] {
+        let len = self.len();
+        let spare = self.capacity() - len;
+
+        unsafe {
+            let ptr = self.as_mut_ptr().add(len) as *mut MaybeUninit<T>;
+            core::slice::from_raw_parts_mut(ptr, spare)
+        }
+    }
+
+    /// Returns the initialized elements and the spare capacity at the same time.
+    ///
+    /// This is same to `(self.as_mut_slice(), self.spare_capacity_mut())` except for that the both
+    /// can be used at once.
+    pub fn split_at_spare_mut(&mut self) -> (&mut [T], &mut [MaybeUninit<T>]) {
+        let len = self.len();
+        let spare = self.capacity() - len;
+
+        unsafe {
+            let ptr = self.as_mut_ptr();
+            let init = core::slice::from_raw_parts_mut(ptr, len);
+            let spare = core::slice::from_raw_parts_mut(ptr.add(len) as *mut MaybeUninit<T>, spare);
+            (init, spare)
+        }
+    }
+
+    /// Appends `count` elements to the spare capacity; the `i` th element is `f(i)` .
+    ///
+    /// `i` is the index of the appended element in `self` , i.e. it starts with `self.len()` .
+    ///
+    /// If `f` panics, the elements created before are kept.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is greater than `self.capacity() - self.len()` .
+    pub fn fill_spare<F>(&mut self, count: usize, mut f: F)
+    where
+        F: FnMut(usize) -> T,
+    {
+        let spare = self.capacity() - self.len();
+        if spare < count {
+            panic!(
+                "count (is {}) should be <= spare capacity (is {})",
+                count, spare
+            );
+        }
+
+        unsafe {
+            let ptr = self.as_mut_ptr();
+            let mut guard = SetLenOnDrop::new(self);
+            let end = guard.len + count;
+
+            while guard.len < end {
+                core::ptr::write(ptr.add(guard.len), f(guard.len));
+                guard.len += 1;
+            }
+        }
+    }
+
+    /// Drops the all elements and appends the elements of `iter` instead.
+    ///
+    /// The allocated capacity is reused; i.e. this method does not reallocate unless `iter` yields
+    /// more elements than the current capacity.
+    pub fn refill<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        self.clear();
+        self.extend(iter);
+    }
+
+    /// Enshortens `self`, keeping the first `new_len` elements and dropping the rest.
+    ///
+    /// If `new_len` is greater than or equals to the current length, nothing is done.
+    ///
+    /// Note that this method does not have any effect on the allocated capacity of `self` .
+    ///
+    /// The removed elements are dropped in order from the front to the back, i.e. `self[new_len]`
+    /// is dropped first and the last element is dropped last, as well as `std::collections::Vec` .
+    /// (So are `clear` and the drop of `self` .)
+    pub fn truncate(&mut self, new_len: usize) {
+        let len = self.len();
+        if len <= new_len {
+            return;
+        }
+
+        // Set the length first in case of panic while dropping.
+        unsafe {
+            self.set_len(new_len);
+            let tail =
+                core::slice::from_raw_parts_mut(self.as_mut_ptr().add(new_len), len - new_len);
+            core::ptr::drop_in_place(tail);
+        }
+    }
+
+    /// Drops the all elements at and after `index` .
+    ///
+    /// This is same to `truncate` except for panicking.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than `self.len()` .
+    pub fn clear_from(&mut self, index: usize) {
+        let len = self.len();
+        if len < index {
+            panic!("index (is {}) should be <= len (is {})", index, len);
+        }
+
+        self.truncate(index);
+    }
+
+    /// Drops the first `index` elements and shifts the rest to the front.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than `self.len()` .
+    pub fn clear_until(&mut self, index: usize) {
+        let len = self.len();
+        if len < index {
+            panic!("index (is {}) should be <= len (is {})", index, len);
+        }
+
+        self.keep_range(index..);
+    }
+
+    /// Keeps only the elements in `range` ; i.e. drops the elements before and after `range` ,
+    /// and shifts the rest to the front.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of `range` is greater than the end, or if the end is greater than
+    /// `self.len()` .
+    pub fn keep_range<R>(&mut self, range: R)
+    where
+        R: RangeBounds<usize>,
+    {
+        let Range { start, end } = to_range(range, self.len());
+
+        self.truncate(end);
+
+        // Set the length 0 first in case of panic while dropping.
+        // (The kept elements are leaked then.)
+        unsafe {
+            self.set_len(0);
+            let ptr = self.as_mut_ptr();
+            core::ptr::drop_in_place(core::ptr::slice_from_raw_parts_mut(ptr, start));
+            core::ptr::copy(ptr.add(start), ptr, end - start);
+            self.set_len(end - start);
+        }
+    }
+
+    /// Shrinks the capacitance of `self` as much as possible.
+    ///
+    /// If the elements fit in the stack buffer, they are moved back to there and the heap memory
+    /// is deallocated. Nothing is done if the capacity already equals to the length.
+    pub fn shrink_to_fit(&mut self) {
+        if self.is_using_stack() {
+            return;
+        }
+
+        let len = self.len();
+        if len <= StackBuffer::<T>::capacity() {
+            unsafe { self.to_stack() };
+        } else if len < self.buffer_capacity() {
+            let alloc = &self.alloc as *const A;
+            unsafe { self.as_mut_heap().set_capacity(len, &*alloc) };
+        }
+    }
+
+    /// Retains only the elements which `f` returns true for.
+    ///
+    /// Each removed element is replaced by the last element; i.e. this method does not preserve
+    /// the order of the elements, and instead, it moves at most one element per removal.
+    pub fn retain_swap<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let mut i = 0;
+        while i < self.len() {
+            if f(&self.as_ref()[i]) {
+                i += 1;
+                continue;
+            }
+
+            // Set the length first in case of panic while dropping.
+            unsafe {
+                let last = self.len() - 1;
+                let ptr = self.as_mut_ptr();
+                let removed = core::ptr::read(ptr.add(i));
+                core::ptr::copy(ptr.add(last), ptr.add(i), 1);
+                self.set_len(last);
+                drop(removed);
+            }
+        }
+    }
+
+    /// Retains only the elements which `f` returns true for, preserving the order of the kept
+    /// elements.
+    ///
+    /// Unlike `retain_swap` , `f` is called for each element from the last one toward the first
+    /// one exactly once. The kept elements are packed toward the end while visiting, and moved to
+    /// the front at last.
+    pub fn retain_rev<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        /// Moves the kept elements to just after the elements not visited yet on drop, so that
+        /// `vec` is consistent even if `f` panics.
+        struct Guard<'a, T, A>
+        where
+            A: GlobalAlloc,
+        {
+            vec: &'a mut SoVec<T, A>,
+            read: usize,
+            write: usize,
+            len: usize,
+        }
+
+        impl<T, A> Drop for Guard<'_, T, A>
+        where
+            A: GlobalAlloc,
+        {
+            fn drop(&mut self) {
+                unsafe {
+                    let ptr = self.vec.as_mut_ptr();
+                    let kept = self.len - self.write;
+                    core::ptr::copy(ptr.add(self.write), ptr.add(self.read), kept);
+                    self.vec.set_len(self.read + kept);
+                }
+            }
+        }
+
+        let len = self.len();
+
+        unsafe {
+            self.set_len(0);
+            let ptr = self.as_mut_ptr();
+            let mut guard = Guard {
+                vec: self,
+                read: len,
+                write: len,
+                len,
+            };
+
+            while 0 < guard.read {
+                let i = guard.read - 1;
+                if f(&*ptr.add(i)) {
+                    guard.write -= 1;
+                    if i != guard.write {
+                        core::ptr::copy_nonoverlapping(ptr.add(i), ptr.add(guard.write), 1);
+                    }
+                    guard.read = i;
+                } else {
+                    guard.read = i;
+                    core::ptr::drop_in_place(ptr.add(i));
+                }
+            }
+        }
+    }
+
+    /// Removes consecutive repeated elements, keeping the first one of each run.
+    pub fn dedup(&mut self)
+    where
+        T: PartialEq,
+    {
+        self.dedup_by(|a, b| a == b);
+    }
+
+    /// Removes consecutive repeated elements as `dedup` does, and returns the number of the
+    /// removed elements.
+    pub fn dedup_count(&mut self) -> usize
+    where
+        T: PartialEq,
+    {
+        let len = self.len();
+        self.dedup();
+        len - self.len()
+    }
+
+    /// Removes consecutive elements which resolve to the same key, keeping the first one of
+    /// each run.
+    pub fn dedup_by_key<K, F>(&mut self, mut key: F)
+    where
+        F: FnMut(&mut T) -> K,
+        K: PartialEq,
+    {
+        self.dedup_by(|a, b| key(a) == key(b));
+    }
+
+    /// Removes consecutive elements which `same_bucket` regards as the same, keeping the first one
+    /// of each run.
+    ///
+    /// `same_bucket` is called with each element and the last kept element before it,
+    /// i.e. `same_bucket(&mut self[i], &mut self[j])` where `j < i` .
+    /// If it returns true, `self[i]` is dropped.
+    ///
+    /// This method visits each element only once, and does not change the capacity.
+    /// The removed elements are dropped immediately in order from the front to the back.
+    /// (So are `dedup` and `dedup_by_key` .)
+    pub fn dedup_by<F>(&mut self, mut same_bucket: F)
+    where
+        F: FnMut(&mut T, &mut T) -> bool,
+    {
+        let len = self.len();
+        if len <= 1 {
+            return;
+        }
+
+        unsafe {
+            let mut compactor = Compactor::new(self);
+            compactor.keep();
+
+            while compactor.read < len {
+                let ptr = compactor.vec.as_mut_ptr();
+                let current = &mut *ptr.add(compactor.read);
+                let prev = &mut *ptr.add(compactor.write - 1);
+
+                if same_bucket(current, prev) {
+                    compactor.discard();
+                } else {
+                    compactor.keep();
+                }
+            }
+        }
+    }
+
+    /// Removes the all duplicated elements (not only consecutive ones,) keeping the first
+    /// occurrence of each value in order.
+    ///
+    /// This method allocates a temporary `HashSet` and a temporary `Vec` via the global
+    /// allocator (not the allocator of `self` .) See also `sort_and_dedup` , which does not
+    /// allocate but does not preserve the order.
+    pub fn unique(&mut self)
+    where
+        T: Hash + Eq,
+    {
+        let keeps: Vec<bool> = {
+            let mut seen = HashSet::with_capacity(self.len());
+            self.as_ref().iter().map(|elm| seen.insert(elm)).collect()
+        };
+
+        unsafe {
+            let mut compactor = Compactor::new(self);
+            for keep in keeps {
+                if keep {
+                    compactor.keep();
+                } else {
+                    compactor.discard();
+                }
+            }
+        }
+    }
+
+    /// Sorts `self` and removes the duplicated elements.
+    ///
+    /// After this method is called, `self` is sorted and holds unique elements.
+    pub fn sort_and_dedup(&mut self)
+    where
+        T: Ord,
+    {
+        self.as_mut().sort();
+        self.dedup();
+    }
+
+    /// Sorts `self` with the key extraction function `f` .
+    ///
+    /// This sort is unstable (i.e. may reorder equal elements,) and does not allocate unlike
+    /// `sort_by_cached_key` .
+    pub fn sort_unstable_by_key<K, F>(&mut self, f: F)
+    where
+        F: FnMut(&T) -> K,
+        K: Ord,
+    {
+        self.as_mut_slice().sort_unstable_by_key(f);
+    }
+
+    /// Removes consecutive elements which `same` regards as the same, keeping the last one of
+    /// each run.
+    ///
+    /// `same` is called with 2 adjacent elements in order, i.e. `same(&self[i], &self[i + 1])` .
+    /// If it returns true, `self[i]` is dropped.
+    pub fn dedup_keep_last_by<F>(&mut self, mut same: F)
+    where
+        F: FnMut(&T, &T) -> bool,
+    {
+        let len = self.len();
+        if len <= 1 {
+            return;
+        }
+
+        unsafe {
+            let mut compactor = Compactor::new(self);
+
+            while compactor.read < len {
+                let ptr = compactor.vec.as_mut_ptr();
+                let read = compactor.read;
+                let keep = read + 1 == len || !same(&*ptr.add(read), &*ptr.add(read + 1));
+
+                if keep {
+                    compactor.keep();
+                } else {
+                    compactor.discard();
+                }
+            }
+        }
+    }
+
+    /// Moves the elements into a new instance using `new_alloc` , and deallocates the heap
+    /// memory `self` is using if any.
+    ///
+    /// The new instance does not allocate heap memory if the elements fit in itself.
+    #[must_use = "the elements are dropped if the returned value is ignored"]
+    pub fn reallocate_in<B>(mut self, new_alloc: B) -> SoVec<T, B>
+    where
+        B: GlobalAlloc,
+    {
+        let len = self.len();
+        let mut ret = SoVec::<T, B>::with_capacity(len, new_alloc);
+        ret.set_max_capacity_unchecked(self.max_capacity());
+
+        unsafe {
+            core::ptr::copy_nonoverlapping(self.as_ptr(), ret.as_mut_ptr(), len);
+            self.set_len(0);
+            ret.set_len(len);
+        }
+
+        ret
+    }
+
+    /// Overwrites the whole buffer (not only the elements but also the spare capacity) with zeros.
+    ///
+    /// Volatile write is used so that the compiler will not elide it.
+    #[cfg(feature = "zeroize")]
+    fn zeroize_buffer(&mut self) {
+        let size = self.buffer_capacity() * core::mem::size_of::<T>();
+        unsafe { crate::zeroize::zeroize(self.as_mut_ptr() as *mut u8, size) };
+    }
+
+    /// Appends elements from `iter` until `self` is full or `iter` is exhausted.
+    ///
+    /// This method never reallocates, and so the capacity is not checked for each element.
+    fn extend_within_capacity<I>(&mut self, iter: &mut I)
+    where
+        I: Iterator<Item = T>,
+    {
+        let capacity = self.capacity();
+
+        unsafe {
+            let ptr = self.as_mut_ptr();
+            let mut guard = SetLenOnDrop::new(self);
+
+            while guard.len < capacity {
+                match iter.next() {
+                    None => break,
+                    Some(elm) => {
+                        core::ptr::write(ptr.add(guard.len), elm);
+                        guard.len += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Clones `self` keeping the capacity.
+    ///
+    /// Unlike `clone` , the capacity of the returned value equals to that of `self` even if it is
+    /// larger than the length.
+    #[must_use]
+    pub fn duplicate_with_capacity(&self) -> Self
+    where
+        T: Clone,
+        A: Clone,
+    {
+        let mut ret = Self::with_capacity(self.capacity(), self.alloc.clone());
+        ret.set_max_capacity_unchecked(self.max_capacity());
+        ret.extend(self.as_ref().iter().cloned());
+        ret
+    }
+
+    /// Splits `self` into 2 at `at` ; i.e. moves the elements at and after `at` into a new
+    /// instance using a clone of the allocator, and returns it.
+    ///
+    /// The capacity of `self` is not changed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at` is greater than `self.len()` .
+    #[must_use = "use `truncate` to just drop the elements"]
+    pub fn split_off(&mut self, at: usize) -> Self
+    where
+        A: Clone,
+    {
+        let alloc = self.alloc.clone();
+        self.split_off_into(at, alloc)
+    }
+
+    /// Shortens `self` to `new_len` and returns the removed elements instead of dropping them.
+    ///
+    /// This is same to `split_off` except for that nothing is done and an empty instance is
+    /// returned if `new_len` is greater than `self.len()` (as `truncate` does.)
+    #[must_use = "use `truncate` to just drop the elements"]
+    pub fn truncate_return(&mut self, new_len: usize) -> Self
+    where
+        A: Clone,
+    {
+        let at = core::cmp::min(new_len, self.len());
+        self.split_off(at)
+    }
+
+    /// Splits `self` into 2 at `at` as `split_off` does, except for that the returned instance
+    /// uses `alloc` .
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at` is greater than `self.len()` .
+    #[must_use = "use `truncate` to just drop the elements"]
+    pub fn split_off_into<B>(&mut self, at: usize, alloc: B) -> SoVec<T, B>
+    where
+        B: GlobalAlloc,
+    {
+        let len = self.len();
+        if len < at {
+            panic!("`at` split index (is {}) should be <= len (is {})", at, len);
+        }
+
+        let count = len - at;
+        let mut ret = SoVec::<T, B>::with_capacity(count, alloc);
+        ret.set_max_capacity_unchecked(self.max_capacity());
+
+        unsafe {
+            core::ptr::copy_nonoverlapping(self.as_ptr().add(at), ret.as_mut_ptr(), count);
+            self.set_len(at);
+            ret.set_len(count);
+        }
+
+        ret
+    }
+
+    /// Splits `self` into 2 at `at` as `split_off` does, except for that the elements at and after
+    /// `at` are moved into `std::collections::Vec` , which uses the global allocator.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at` is greater than `self.len()` .
+    #[must_use = "use `truncate` to just drop the elements"]
+    pub fn split_off_vec(&mut self, at: usize) -> Vec<T> {
+        let len = self.len();
+        if len < at {
+            panic!("`at` split index (is {}) should be <= len (is {})", at, len);
+        }
+
+        let count = len - at;
+        let mut ret = Vec::with_capacity(count);
+
+        unsafe {
+            core::ptr::copy_nonoverlapping(self.as_ptr().add(at), ret.as_mut_ptr(), count);
+            self.set_len(at);
+            ret.set_len(count);
+        }
+
+        ret
+    }
+
+    /// Clones the all elements into a new `Vec` , leaving `self` as it is.
+    ///
+    /// The `Vec` is allocated by the global allocator regardless of the allocator of `self` .
+    #[must_use]
+    pub fn to_vec(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        self.as_ref().to_vec()
+    }
+
+    /// Moves the all elements into a new `Arc<[T]>` to share them immutably.
+    ///
+    /// The heap memory of `self` (if any) cannot be reused because `Arc` places the reference
+    /// counts in front of the elements; i.e. this method allocates once by the global allocator,
+    /// moves the elements (not clones,) and deallocates the buffer of `self` .
+    ///
+    /// This is available only if feature `std` is enabled.
+    #[cfg(feature = "std")]
+    #[must_use = "the elements are dropped if the returned value is ignored"]
+    pub fn into_arc_slice(mut self) -> std::sync::Arc<[T]> {
+        let len = self.len();
+        let mut ret = std::sync::Arc::<[T]>::new_uninit_slice(len);
+
+        unsafe {
+            let dst = std::sync::Arc::get_mut(&mut ret).unwrap_unchecked();
+            core::ptr::copy_nonoverlapping(self.as_ptr(), dst.as_mut_ptr() as *mut T, len);
+            self.set_len(0);
+            ret.assume_init()
+        }
+    }
+
+    /// Moves the all elements into a new instance and returns it, leaving `self` empty.
+    ///
+    /// The heap buffer (if any) is moved as it is, and `self` becomes a new instance using
+    /// a clone of the allocator. This method neither moves each element nor allocates.
+    #[must_use = "use `clear` to just drop the elements"]
+    pub fn take_all(&mut self) -> Self
+    where
+        A: Clone,
+    {
+        let mut empty = Self::from(self.alloc.clone());
+        empty.set_max_capacity_unchecked(self.max_capacity());
+        core::mem::replace(self, empty)
+    }
+
+    /// Clones the elements in `range` into a new instance using a clone of the allocator.
+    ///
+    /// The new instance has the minimum capacity to hold the elements as `clone` does.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of `range` is greater than the end, or if the end is greater than
+    /// `self.len()` .
+    #[must_use]
+    pub fn sub<R>(&self, range: R) -> Self
+    where
+        R: RangeBounds<usize>,
+        T: Clone,
+        A: Clone,
+    {
+        let range = to_range(range, self.len());
+        let mut ret =
+            Self::from_exact_iter(self.as_ref()[range].iter().cloned(), self.alloc.clone());
+        ret.set_max_capacity_unchecked(self.max_capacity());
+        ret
+    }
+
+    /// Clones the elements into a new instance using `alloc` .
+    ///
+    /// This is useful when `A` is a borrowed allocator (e.g. `&'a SomeAlloc` ) and the elements
+    /// should outlive the borrow.
+    #[must_use]
+    pub fn to_owned_alloc<B>(&self, alloc: B) -> SoVec<T, B>
+    where
+        T: Clone,
+        B: GlobalAlloc,
+    {
+        let mut ret = SoVec::<T, B>::with_capacity(self.len(), alloc);
+        ret.set_max_capacity_unchecked(self.max_capacity());
+        ret.extend(self.as_ref().iter().cloned());
+        ret
+    }
+
+    /// Calls `f` with a mutable reference to each element in order.
+    ///
+    /// Unlike `map_in_place` , `f` modifies each element through the reference.
+    pub fn apply<F>(&mut self, f: F)
+    where
+        F: FnMut(&mut T),
+    {
+        self.as_mut_slice().iter_mut().for_each(f);
+    }
+
+    /// Moves each element into `f` in order, and deallocates the heap memory if any.
+    ///
+    /// This is same to `self.into_iter().for_each(f)` except for that no iterator is constructed.
+    /// If `f` panics, the rest of the elements are dropped and the heap memory is deallocated as
+    /// well.
+    pub fn for_each_consume<F>(mut self, mut f: F)
+    where
+        F: FnMut(T),
+    {
+        /// Shifts the elements after `index` (i.e. not passed to `f` yet) to the front on drop, so
+        /// that they are dropped with `vec` .
+        struct Guard<'a, T, A>
+        where
+            A: GlobalAlloc,
+        {
+            vec: &'a mut SoVec<T, A>,
+            index: usize,
+            len: usize,
+        }
+
+        impl<T, A> Drop for Guard<'_, T, A>
+        where
+            A: GlobalAlloc,
+        {
+            fn drop(&mut self) {
+                unsafe {
+                    let ptr = self.vec.as_mut_ptr();
+                    core::ptr::copy(ptr.add(self.index), ptr, self.len - self.index);
+                    self.vec.set_len(self.len - self.index);
+                }
+            }
+        }
+
+        let len = self.len();
+
+        unsafe {
+            self.set_len(0);
+            let ptr = self.as_mut_ptr();
+            let mut guard = Guard {
+                vec: &mut self,
+                index: 0,
+                len,
+            };
+
+            while guard.index < len {
+                let elm = core::ptr::read(ptr.add(guard.index));
+                guard.index += 1;
+                f(elm);
+            }
+        }
+    }
+
+    /// Replaces each element with `T::default()` in place.
+    ///
+    /// Unlike `clear` , neither the length nor the capacity is changed.
+    pub fn reset(&mut self)
+    where
+        T: Default,
+    {
+        self.apply(|elm| *elm = T::default());
+    }
+
+    /// Replaces each element with `f(element)` in place.
+    ///
+    /// If `f` panics, the element passed to `f` is lost, and the other elements are kept in `self`
+    /// (the elements before it are already replaced.) Each element is dropped exactly once anyway.
+    pub fn map_in_place<F>(&mut self, mut f: F)
+    where
+        F: FnMut(T) -> T,
+    {
+        /// Removes the element at `index` (which is being passed to `f` ) on drop if any.
+        struct Guard<'a, T, A>
+        where
+            A: GlobalAlloc,
+        {
+            vec: &'a mut SoVec<T, A>,
+            index: usize,
+            len: usize,
+        }
+
+        impl<T, A> Drop for Guard<'_, T, A>
+        where
+            A: GlobalAlloc,
+        {
+            fn drop(&mut self) {
+                unsafe {
+                    if self.index < self.len {
+                        let ptr = self.vec.as_mut_ptr().add(self.index);
+                        core::ptr::copy(ptr.add(1), ptr, self.len - self.index - 1);
+                        self.vec.set_len(self.len - 1);
+                    } else {
+                        self.vec.set_len(self.len);
+                    }
+                }
+            }
+        }
+
+        let len = self.len();
+
+        unsafe {
+            self.set_len(0);
+            let ptr = self.as_mut_ptr();
+            let mut guard = Guard {
+                vec: self,
+                index: 0,
+                len,
+            };
+
+            while guard.index < len {
+                let elm = core::ptr::read(ptr.add(guard.index));
+                core::ptr::write(ptr.add(guard.index), f(elm));
+                guard.index += 1;
+            }
+        }
+    }
+
+    /// Takes the heap memory and the allocator out of `self` without dropping the elements nor
+    /// deallocating the memory, allocating the heap first if `self` holds the elements in the
+    /// stack buffer.
+    ///
+    /// The caller takes responsibility for them; `SoVec::from_heap_handle` reconstructs the
+    /// instance from the returned values.
+    ///
+    /// If `self` is using the stack buffer, the elements are moved to the heap allocated with the
+    /// minimum capacity so that they are not lost; i.e. this method is a zero-copy teardown only
+    /// if `self` has spilled. No handle is returned if `self` is empty and using the stack buffer.
+    ///
+    /// The spill observer is dropped, and it is not called on the allocation above.
+    ///
+    /// # Panics
+    ///
+    /// Panics if failed to allocate the heap.
+    #[must_use = "the elements and the heap memory leak if the returned value is ignored"]
+    pub fn dismantle(mut self) -> (Option<HeapHandle<T>>, A) {
+        // Moving the elements to the heap below is not a spill.
+        #[cfg(feature = "spill-observer")]
+        drop(self.spill_observer.take());
+
+        let len = self.len();
+        if self.is_using_stack() && 0 < len {
+            unsafe {
+                let mut heap_buffer = HeapBuffer::<T>::try_with_capacity(len, &self.alloc)
+                    .unwrap_or_else(|e| e.handle());
+                core::ptr::copy_nonoverlapping(self.as_ptr(), heap_buffer.as_mut_ptr(), len);
+                heap_buffer.set_len(len);
+                self.set_len(0);
+                self.to_heap(heap_buffer);
+            }
+        }
+
+        // The spill observer has been taken, so nothing is left to drop but the allocator.
+        let this = ManuallyDrop::new(self);
+
+        unsafe {
+            let handle = if this.is_using_stack() {
+                None
+            } else {
+                Some(HeapHandle::new(
+                    core::ptr::read(this.as_heap()),
+                    this.max_capacity(),
+                ))
+            };
+
+            (handle, core::ptr::read(&this.alloc))
+        }
+    }
+
+    /// Drops the all elements, deallocates the heap memory if any, and returns the allocator.
+    pub(crate) fn into_allocator(self) -> A {
+        let mut this = ManuallyDrop::new(self);
+
+        unsafe {
+            this.release();
+
+            #[cfg(feature = "spill-observer")]
+            core::ptr::drop_in_place(&mut this.spill_observer);
+
+            core::ptr::read(&this.alloc)
+        }
+    }
+
+    /// Drops the all elements and deallocates the heap memory if any.
+    ///
+    /// # Safety
+    ///
+    /// `self` must not be used after this method is called.
+    unsafe fn release(&mut self) {
+        self.clear();
+
+        #[cfg(feature = "zeroize")]
+        self.zeroize_buffer();
+
+        if !self.is_using_stack() {
+            let alloc = &self.alloc as *const A;
+            self.as_mut_heap().pre_drop(&*alloc);
+        }
+    }
+
+    /// Sets the max capacity without checking the length.
+    ///
+    /// It is used to make the instances derived from another one inherit the max capacity.
+    /// Nothing is done unless feature `max-capacity` is enabled.
+    #[cfg_attr(not(feature = "max-capacity"), allow(unused_variables))]
+    fn set_max_capacity_unchecked(&mut self, max_capacity: usize) {
+        #[cfg(feature = "max-capacity")]
+        {
+            self.max_capacity = max_capacity;
+        }
+    }
+
+    /// Returns the number of the elements the buffer can hold regardless of `max_capacity` .
+    fn buffer_capacity(&self) -> usize {
+        if self.is_using_stack() {
+            StackBuffer::<T>::capacity()
+        } else {
+            self.as_heap().capacity()
+        }
+    }
+
+    /// Returns true if `self` is using StackBuffer; otherwise, i.e. `self` is using `HeapBuffer`,
+    /// returns false.
+    fn is_using_stack(&self) -> bool {
+        self.buffer.is_available()
+    }
+
+    /// Returns `self.bufer` .
+    fn as_stack(&self) -> &StackBuffer<T> {
+        debug_assert!(self.is_using_stack());
+        &self.buffer
+    }
+
+    /// Returns `self.bufer` .
+    fn as_mut_stack(&mut self) -> &mut StackBuffer<T> {
+        debug_assert!(self.is_using_stack());
+        &mut self.buffer
+    }
+
+    /// Forces to regards `self.buffer` as HeapBuffer and returns it.
+    fn as_heap(&self) -> &HeapBuffer<T> {
+        debug_assert!(!self.is_using_stack());
+        let ptr = &self.buffer as *const StackBuffer<T>;
+        let ptr = ptr as *const u8;
+        let ptr = ptr as *const HeapBuffer<T>;
+        unsafe { &*ptr }
+    }
+
+    /// Forces to regards `self.buffer` as HeapBuffer and returns it.
+    fn as_mut_heap(&mut self) -> &mut HeapBuffer<T> {
+        debug_assert!(!self.is_using_stack());
+        let ptr = &mut self.buffer as *mut StackBuffer<T>;
+        let ptr = ptr as *mut u8;
+        let ptr = ptr as *mut HeapBuffer<T>;
+        unsafe { &mut *ptr }
+    }
+
+    /// Disables small optimization forces to overwrite `self.stack` .
+    ///
+    /// Note that this method does not move each element.
+    #[allow(clippy::wrong_self_convention)]
+    unsafe fn to_heap(&mut self, new_buffer: HeapBuffer<T>) {
+        debug_assert!(self.is_using_stack());
+
+        // The elements have been moved to the heap, but the bytes are left in the stack buffer.
+        #[cfg(feature = "zeroize")]
+        self.zeroize_buffer();
+
+        let ptr = &mut self.buffer as *mut StackBuffer<T>;
+        let ptr = ptr as *mut u8;
+        let ptr = ptr as *mut HeapBuffer<T>;
+
+        core::ptr::write(ptr, new_buffer);
+        self.as_mut_stack().disable();
+
+        #[cfg(debug_assertions)]
+        self.check_state(false);
+
+        #[cfg(feature = "spill-observer")]
+        {
+            if let Some(f) = self.spill_observer.as_ref() {
+                f(self.buffer_capacity());
+            }
+        }
+    }
+
+    /// Moves the elements from the heap to the stack buffer, and deallocates the heap memory.
+    ///
+    /// # Safety
+    ///
+    /// `self` must be using `HeapBuffer` and the elements must fit in `StackBuffer` .
+    #[allow(clippy::wrong_self_convention)]
+    unsafe fn to_stack(&mut self) {
+        debug_assert!(!self.is_using_stack());
+        debug_assert!(self.len() <= StackBuffer::<T>::capacity());
+
+        let alloc = &self.alloc as *const A;
+        let mut heap_buffer = core::ptr::read(self.as_heap());
+        let len = heap_buffer.len();
+
+        let mut stack_buffer = StackBuffer::<T>::new();
+        core::ptr::copy_nonoverlapping(heap_buffer.as_ptr(), stack_buffer.as_mut_ptr(), len);
+        stack_buffer.set_len(len);
+
+        heap_buffer.set_len(0);
+
+        #[cfg(feature = "zeroize")]
+        crate::zeroize::zeroize(
+            heap_buffer.as_mut_ptr() as *mut u8,
+            heap_buffer.capacity() * core::mem::size_of::<T>(),
+        );
+
+        heap_buffer.pre_drop(&*alloc);
+        core::ptr::write(&mut self.buffer, stack_buffer);
+
+        #[cfg(debug_assertions)]
+        self.check_state(true);
+    }
+
+    /// Checks that `self.buffer` is consistent with the expected state, and panics if not.
+    ///
+    /// `using_stack` is true if `self` should be using `StackBuffer` , or false.
+    #[cfg(debug_assertions)]
+    fn check_state(&self, using_stack: bool) {
+        if using_stack {
+            assert!(
+                self.is_using_stack(),
+                "SoVec is expected to use StackBuffer, but the discriminant says HeapBuffer."
+            );
+            assert!(self.as_stack().len() <= StackBuffer::<T>::capacity());
+        } else {
+            assert!(
+                !self.is_using_stack(),
+                "SoVec is expected to use HeapBuffer, but the discriminant says StackBuffer."
+            );
+
+            let heap = self.as_heap();
+            assert!(!heap.as_ptr().is_null(), "HeapBuffer has null pointer.");
+            assert!(heap.len() <= heap.capacity());
+        }
+    }
+}
+
+impl<T, A> SoVec<T, A>
+where
+    T: Copy,
+    A: GlobalAlloc,
+{
+    /// Returns true if the elements of `self` and `other` are the same bytes, or false.
+    ///
+    /// This method compares the memory at once (as `memcmp` does) instead of calling
+    /// `PartialEq::eq` for each element; i.e. `T` need not implement `PartialEq` , and the result
+    /// can differ from `==` . (e.g. `f32::NAN` is the same bytes as itself, while `0.0_f32` and
+    /// `-0.0_f32` are not.)
+    ///
+    /// # Safety
+    ///
+    /// `T` must not have any padding byte, because reading it is undefined behavior.
+    pub unsafe fn bytes_eq<B>(&self, other: &SoVec<T, B>) -> bool
+    where
+        B: GlobalAlloc,
+    {
+        if self.len() != other.len() {
+            return false;
+        }
+
+        let size = self.len() * core::mem::size_of::<T>();
+        let this = core::slice::from_raw_parts(self.as_slice().as_ptr() as *const u8, size);
+        let other = core::slice::from_raw_parts(other.as_slice().as_ptr() as *const u8, size);
+
+        // `[u8]::eq` is specialized to compare the memory at once.
+        this == other
+    }
+}
+
+impl<A> SoVec<u8, A>
+where
+    A: GlobalAlloc,
+{
+    /// Overwrites the all elements with 0 at once.
+    ///
+    /// Neither the length nor the capacity is changed.
+    pub fn fill_zeroed(&mut self) {
+        let len = self.len();
+        unsafe { core::ptr::write_bytes(self.as_mut_ptr(), 0, len) };
+    }
+
+    /// Returns the bytes as `&str` if they are valid UTF-8; otherwise, returns an error.
+    ///
+    /// The bytes are not copied.
+    pub fn as_str(&self) -> Result<&str, core::str::Utf8Error> {
+        core::str::from_utf8(self.as_ref())
+    }
+
+    /// Returns the bytes as `&str` without checking that they are valid UTF-8.
+    ///
+    /// # Safety
+    ///
+    /// The bytes must be valid UTF-8.
+    pub unsafe fn as_str_unchecked(&self) -> &str {
+        core::str::from_utf8_unchecked(self.as_ref())
+    }
+}
+
+impl<T, A, B> SoVec<SoVec<T, B>, A>
+where
+    A: GlobalAlloc,
+    B: GlobalAlloc,
+{
+    /// Concatenates the inner vectors into a new instance using a clone of the allocator.
+    ///
+    /// The total length is reserved at once, and then the elements are moved (not cloned.)
+    /// Each inner buffer is deallocated just after its elements are moved.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the total length overflows `usize` .
+    #[must_use = "the elements are dropped if the returned value is ignored"]
+    pub fn flatten(self) -> SoVec<T, A>
+    where
+        A: Clone,
+    {
+        let total = self
+            .iter()
+            .try_fold(0_usize, |acc, inner| acc.checked_add(inner.len()))
+            .expect("capacity overflow");
+        let mut ret = SoVec::<T, A>::with_capacity(total, self.alloc.clone());
+
+        for mut inner in self {
+            unsafe {
+                let len = inner.len();
+                core::ptr::copy_nonoverlapping(
+                    inner.as_ptr(),
+                    ret.as_mut_ptr().add(ret.len()),
+                    len,
+                );
+                inner.set_len(0);
+                ret.set_len(ret.len() + len);
+            }
+        }
+
+        ret
+    }
+}
+
+/// `write` reserves the capacity geometrically (i.e. calls `try_reserve` rather than
+/// `try_reserve_exact` ,) so writing many small chunks does not reallocate so many times.
+///
+/// `write` never exceeds `max_capacity` ; it writes as many bytes as `self` can hold, and returns
+/// `Ok(0)` if `self` is full. (Then `write_all` returns an error of `ErrorKind::WriteZero` .)
+/// It returns an error of `ErrorKind::OutOfMemory` if the allocator fails.
+impl<A> std::io::Write for SoVec<u8, A>
+where
+    A: GlobalAlloc,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = core::cmp::min(buf.len(), self.max_capacity() - self.len());
+        self.try_reserve(n).map_err(|e| {
+            let kind = match e {
+                TryReserveError::AllocError { .. } => std::io::ErrorKind::OutOfMemory,
+                _ => std::io::ErrorKind::Other,
+            };
+            std::io::Error::new(kind, e)
+        })?;
+
+        unsafe { self.extend_from_slice_unchecked(&buf[..n]) };
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Converts `range` into `Range<usize>` checking the bounds.
+///
+/// # Panics
+///
+/// Panics if the start of `range` is greater than the end, or if the end is greater than `len` .
+fn to_range<R>(range: R, len: usize) -> Range<usize>
+where
+    R: RangeBounds<usize>,
+{
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start
+            .checked_add(1)
+            .expect("attempted to index slice from after maximum usize"),
+        Bound::Unbounded => 0,
+    };
+
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end
+            .checked_add(1)
+            .expect("attempted to index slice up to maximum usize"),
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded => len,
+    };
+
+    if end < start {
+        panic!("slice index starts at {} but ends at {}", start, end);
+    }
+    if len < end {
+        panic!(
+            "range end index {} out of range for slice of length {}",
+            end, len
+        );
+    }
+
+    start..end
+}
+
+/// Helper to update the length of `SoVec` only once, even if the caller panics in the middle.
+///
+/// The length is set to `len` on drop.
+struct SetLenOnDrop<'a, T, A>
+where
+    A: GlobalAlloc,
+{
+    vec: &'a mut SoVec<T, A>,
+    len: usize,
+}
+
+impl<'a, T, A> SetLenOnDrop<'a, T, A>
+where
+    A: GlobalAlloc,
+{
+    /// Creates a new instance whose `len` is the current length of `vec` .
+    fn new(vec: &'a mut SoVec<T, A>) -> Self {
+        let len = vec.len();
+        Self { vec, len }
+    }
+}
+
+impl<T, A> Drop for SetLenOnDrop<'_, T, A>
+where
+    A: GlobalAlloc,
+{
+    fn drop(&mut self) {
+        unsafe { self.vec.set_len(self.len) };
+    }
+}
+
+/// Helper to remove some elements from `SoVec` in place.
+///
+/// `Compactor` visits each element from the front, and either keeps (moves it toward the front)
+/// or discards (drops) it.
+/// The not-visited elements are shifted after the kept ones and the length is fixed on drop,
+/// even if the caller panics in the middle.
+struct Compactor<'a, T, A>
+where
+    A: GlobalAlloc,
+{
+    vec: &'a mut SoVec<T, A>,
+    read: usize,
+    write: usize,
+    len: usize,
+}
+
+impl<'a, T, A> Compactor<'a, T, A>
+where
+    A: GlobalAlloc,
+{
+    /// Creates a new instance.
+    ///
+    /// The length of `vec` is set to 0 while `Compactor` is alive.
+    unsafe fn new(vec: &'a mut SoVec<T, A>) -> Self {
+        let len = vec.len();
+        vec.set_len(0);
+
+        Self {
+            vec,
+            read: 0,
+            write: 0,
+            len,
+        }
+    }
+
+    /// Keeps the current element and visits the next one.
+    unsafe fn keep(&mut self) {
+        debug_assert!(self.read < self.len);
+
+        let ptr = self.vec.as_mut_ptr();
+        if self.read != self.write {
+            core::ptr::copy_nonoverlapping(ptr.add(self.read), ptr.add(self.write), 1);
+        }
+
+        self.read += 1;
+        self.write += 1;
+    }
+
+    /// Drops the current element and visits the next one.
+    unsafe fn discard(&mut self) {
+        debug_assert!(self.read < self.len);
+
+        let ptr = self.vec.as_mut_ptr().add(self.read);
+        self.read += 1;
+        core::ptr::drop_in_place(ptr);
+    }
+}
+
+impl<T, A> Drop for Compactor<'_, T, A>
+where
+    A: GlobalAlloc,
+{
+    fn drop(&mut self) {
+        unsafe {
+            let ptr = self.vec.as_mut_ptr();
+            let rest = self.len - self.read;
+            core::ptr::copy(ptr.add(self.read), ptr.add(self.write), rest);
+            self.vec.set_len(self.write + rest);
+        }
+    }
+}
+
+impl<T, A> From<A> for SoVec<T, A>
+where
+    A: GlobalAlloc,
+{
+    fn from(alloc: A) -> Self {
+        let ret = Self {
+            buffer: StackBuffer::<T>::new(),
+            alloc,
+            #[cfg(feature = "max-capacity")]
+            max_capacity: usize::MAX,
+            #[cfg(feature = "spill-observer")]
+            spill_observer: None,
+        };
+
+        #[cfg(debug_assertions)]
+        ret.check_state(true);
+
+        ret
+    }
+}
+
+/// Takes the heap memory of `vec` without moving the elements; see `SoVec::from_vec` .
+///
+/// `SoVec` keeps using the heap even if the elements fit in the stack buffer.
+/// Call `SoVec::from_vec_compacting` to regain the small optimization.
+impl<T> From<Vec<T>> for SoVec<T, Global> {
+    fn from(vec: Vec<T>) -> Self {
+        // `Vec` is allocated by the global allocator, which `Global` forwards to.
+        unsafe { Self::from_vec(vec, Global) }
+    }
+}
+
+/// The clone has the minimum capacity to hold the elements (it uses the stack buffer if the
+/// elements fit in,) regardless of the capacity of `self` .
+/// Call `duplicate_with_capacity` to keep the capacity.
+impl<T, A> Clone for SoVec<T, A>
+where
+    T: Clone,
+    A: GlobalAlloc + Clone,
+{
+    fn clone(&self) -> Self {
+        let mut ret = Self::with_capacity(self.len(), self.alloc.clone());
+        ret.set_max_capacity_unchecked(self.max_capacity());
+        ret.extend(self.as_ref().iter().cloned());
+        ret
+    }
+}
+
+impl<T, U, A, B> PartialEq<SoVec<U, B>> for SoVec<T, A>
+where
+    T: PartialEq<U>,
+    A: GlobalAlloc,
+    B: GlobalAlloc,
+{
+    fn eq(&self, other: &SoVec<U, B>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<T, A> Eq for SoVec<T, A>
+where
+    T: Eq,
+    A: GlobalAlloc,
+{
+}
+
+/// `{:?}` shows only the elements as `std::collections::Vec` does.
+///
+/// If feature `debug-internals` is enabled, `{:#?}` shows whether `self` has spilled and the
+/// capacity as well.
+impl<T, A> fmt::Debug for SoVec<T, A>
+where
+    T: fmt::Debug,
+    A: GlobalAlloc,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        #[cfg(feature = "debug-internals")]
+        {
+            if f.alternate() {
+                return f
+                    .debug_struct("SoVec")
+                    .field("elements", &self.as_slice())
+                    .field("spilled", &self.spilled())
+                    .field("capacity", &self.capacity())
+                    .finish();
+            }
+        }
+
+        f.debug_list().entries(self.as_slice()).finish()
+    }
+}
+
+/// Creates a new empty instance using `A::default()` .
+///
+/// `std::alloc::System` implements `Default` , so the most common case does not need to pass the
+/// allocator explicitly.
+///
+/// ```
+/// use mouse_sovec::SoVec;
+/// use std::alloc::System;
+///
+/// let mut v = SoVec::<u8, System>::default();
+/// v.push_grow(1);
+/// assert_eq!(&[1], v.as_ref());
+/// ```
+impl<T, A> Default for SoVec<T, A>
+where
+    A: GlobalAlloc + Default,
+{
+    fn default() -> Self {
+        Self::from(A::default())
+    }
+}
+
+impl<T, A> FromIterator<T> for SoVec<T, A>
+where
+    A: GlobalAlloc + Default,
+{
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        Self::from_iter_in(iter, A::default())
+    }
+}
+
+impl<T, A> AsRef<[T]> for SoVec<T, A>
+where
+    A: GlobalAlloc,
+{
+    fn as_ref(&self) -> &[T] {
+        unsafe { core::slice::from_raw_parts(self.as_ptr(), self.len()) }
+    }
+}
+
+impl<T, A> AsMut<[T]> for SoVec<T, A>
+where
+    A: GlobalAlloc,
+{
+    fn as_mut(&mut self) -> &mut [T] {
+        unsafe { core::slice::from_raw_parts_mut(self.as_mut_ptr(), self.len()) }
+    }
+}
+
+impl<T, A> Deref for SoVec<T, A>
+where
+    A: GlobalAlloc,
+{
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_ref()
+    }
+}
+
+impl<T, A> DerefMut for SoVec<T, A>
+where
+    A: GlobalAlloc,
+{
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut()
+    }
+}
+
+impl<T, A> Extend<T> for SoVec<T, A>
+where
+    A: GlobalAlloc,
+{
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let mut iter = iter.into_iter();
+
+        // If the size of `iter` is known exactly, the first allocation fits it. After that, the
+        // capacity grows geometrically so that repeated small extends are amortized O(1).
+        match iter.size_hint() {
+            (lower, Some(upper)) if lower == upper && self.is_using_stack() => {
+                self.reserve_exact(lower)
+            }
+            (lower, _) => self.reserve(lower),
+        }
+
+        // If `iter` is an `ExactSizeIterator` , the first call of `extend_within_capacity` consumes
+        // all the elements and the loop ends without any more reservation.
+        loop {
+            self.extend_within_capacity(&mut iter);
+
+            match iter.next() {
+                None => break,
+                Some(elm) => {
+                    let (lower, _) = iter.size_hint();
+                    self.reserve(lower.saturating_add(1));
+                    unsafe { self.push(elm) };
+                }
+            }
+        }
+    }
+}
+
+impl<T, A> IntoIterator for SoVec<T, A>
+where
+    A: GlobalAlloc,
+{
+    type Item = T;
+    type IntoIter = IntoIter<T, A>;
+
+    fn into_iter(self) -> IntoIter<T, A> {
+        IntoIter::new(self)
+    }
+}
+
+impl<'a, T, A> IntoIterator for &'a SoVec<T, A>
+where
+    A: GlobalAlloc,
+{
+    type Item = &'a T;
+    type IntoIter = core::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.as_slice().iter()
+    }
+}
+
+impl<'a, T, A> IntoIterator for &'a mut SoVec<T, A>
+where
+    A: GlobalAlloc,
+{
+    type Item = &'a mut T;
+    type IntoIter = core::slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.as_mut_slice().iter_mut()
+    }
+}
+
+impl<T, A> Drop for SoVec<T, A>
+where
+    A: GlobalAlloc,
+{
+    fn drop(&mut self) {
+        unsafe { self.release() };
+    }
+}
+
+/// Checks that ignoring the return value of the `#[must_use]` methods fails to compile.
+///
+/// ```compile_fail
+/// #![deny(unused_must_use)]
+/// let mut v = mouse_sovec::SoVec::<u8, std::alloc::System>::default();
+/// v.extend(Some(0));
+/// v.pop();
+/// ```
+///
+/// ```compile_fail
+/// #![deny(unused_must_use)]
+/// let mut v = mouse_sovec::SoVec::<u8, std::alloc::System>::default();
+/// v.push_checked(0);
+/// ```
+///
+/// ```compile_fail
+/// #![deny(unused_must_use)]
+/// let mut v = mouse_sovec::SoVec::<u8, std::alloc::System>::default();
+/// v.try_reserve(1);
+/// ```
+///
+/// ```compile_fail
+/// #![deny(unused_must_use)]
+/// let mut v = mouse_sovec::SoVec::<u8, std::alloc::System>::default();
+/// v.take_all();
+/// ```
+///
+/// ```compile_fail
+/// #![deny(unused_must_use)]
+/// let mut v = mouse_sovec::SoVec::<u8, std::alloc::System>::default();
+/// v.extend(Some(0));
+/// v.remove(0);
+/// ```
+///
+/// ```compile_fail
+/// #![deny(unused_must_use)]
+/// let mut v = mouse_sovec::SoVec::<u8, std::alloc::System>::default();
+/// v.pop_front();
+/// ```
+///
+/// ```compile_fail
+/// #![deny(unused_must_use)]
+/// let mut v = mouse_sovec::SoVec::<u8, std::alloc::System>::default();
+/// v.extend(Some(0));
+/// v.replace(0, 1);
+/// ```
+///
+/// ```compile_fail
+/// #![deny(unused_must_use)]
+/// let v = mouse_sovec::SoVec::<u8, std::alloc::System>::default();
+/// v.to_vec();
+/// ```
+///
+/// ```compile_fail
+/// #![deny(unused_must_use)]
+/// let v = mouse_sovec::SoVec::<u8, std::alloc::System>::default();
+/// v.into_arc_slice();
+/// ```
+///
+/// ```compile_fail
+/// #![deny(unused_must_use)]
+/// let mut v = mouse_sovec::SoVec::<u8, std::alloc::System>::default();
+/// v.split_off_into(0, std::alloc::System);
+/// ```
+///
+/// ```compile_fail
+/// #![deny(unused_must_use)]
+/// let v = mouse_sovec::SoVec::<u8, std::alloc::System>::default();
+/// v.sub(..);
+/// ```
+///
+/// ```compile_fail
+/// #![deny(unused_must_use)]
+/// let v = mouse_sovec::SoVec::<u8, std::alloc::System>::default();
+/// v.dismantle();
+/// ```
+///
+/// The same code compiles if the return values are used.
+///
+/// ```
+/// #![deny(unused_must_use)]
+/// let mut v = mouse_sovec::SoVec::<u8, std::alloc::System>::default();
+/// v.extend(Some(0));
+/// let _ = v.pop();
+/// let _ = v.push_checked(0);
+/// let _ = v.try_reserve(1);
+/// let _ = v.take_all();
+/// v.extend(Some(0));
+/// let _ = v.remove(0);
+/// let _ = v.pop_front();
+/// v.extend(Some(0));
+/// let _ = v.replace(0, 1);
+/// let _ = v.to_vec();
+/// let _ = v.split_off_into(0, std::alloc::System);
+/// let _ = v.sub(..);
+/// let _ = v.dismantle();
+/// ```
+#[cfg(doctest)]
+pub struct MustUse;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::allocator::TestAllocator;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Records its id into `log` on drop.
+    struct DropLogger {
+        id: usize,
+        log: Rc<RefCell<Vec<usize>>>,
+    }
+
+    impl Drop for DropLogger {
+        fn drop(&mut self) {
+            self.log.borrow_mut().push(self.id);
+        }
+    }
+
+    #[test]
+    fn debug() {
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        assert_eq!("[]", format!("{:?}", v));
+
+        v.extend(0..3);
+        assert_eq!("[0, 1, 2]", format!("{:?}", v));
+
+        #[cfg(not(feature = "debug-internals"))]
+        assert_eq!(format!("{:#?}", vec![0, 1, 2]), format!("{:#?}", v));
+
+        #[cfg(feature = "debug-internals")]
+        {
+            // Mirrors the output of `{:#?}` .
+            #[derive(Debug)]
+            #[allow(dead_code)]
+            struct SoVec<'a> {
+                elements: &'a [u8],
+                spilled: bool,
+                capacity: usize,
+            }
+
+            let expected = SoVec {
+                elements: &[0, 1, 2],
+                spilled: false,
+                capacity: StackBuffer::<u8>::capacity(),
+            };
+            assert_eq!(format!("{:#?}", expected), format!("{:#?}", v));
+
+            v.extend(3..100);
+            assert_eq!(
+                format!("{:?}", (0..100).collect::<Vec<u8>>()),
+                format!("{:?}", v)
+            );
+            let s = format!("{:#?}", v);
+            assert!(s.contains("spilled: true"));
+            assert!(s.contains(&format!("capacity: {},", v.capacity())));
+        }
+    }
+
+    #[test]
+    fn assert_eq_message() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        v.extend(1..=3);
+        let mut w = SoVec::<u8, TestAllocator>::default();
+        w.extend(0..100);
+
+        let payload = catch_unwind(AssertUnwindSafe(|| assert_eq!(v, w))).unwrap_err();
+        let message = payload.downcast_ref::<String>().unwrap();
+
+        assert!(message.contains(&format!("{:?}", vec![1, 2, 3])));
+        assert!(message.contains(&format!("{:?}", (0..100).collect::<Vec<u8>>())));
+        assert!(!message.contains("capacity"));
+        assert!(!message.contains("spilled"));
+    }
+
+    #[test]
+    fn from() {
+        {
+            let alloc = TestAllocator::new();
+            let v = SoVec::<u8, TestAllocator>::from(alloc);
+
+            assert_eq!(0, v.len());
+        }
+
+        {
+            let alloc = TestAllocator::new();
+            let v = SoVec::<String, TestAllocator>::from(alloc);
+
+            assert_eq!(0, v.len());
+        }
+
+        {
+            let alloc = TestAllocator::new();
+            let v = SoVec::<[u8; 3], TestAllocator>::from(alloc);
+
+            assert_eq!(0, v.len());
+        }
+    }
+
+    #[test]
+    fn with_capacity() {
+        for i in 0..(StackBuffer::<u8>::capacity() + 10) {
+            let alloc = TestAllocator::new();
+            let v = SoVec::<u8, TestAllocator>::with_capacity(i, alloc);
+
+            assert_eq!(0, v.len());
+            assert!(i <= v.capacity());
+        }
+
+        for i in 0..(StackBuffer::<String>::capacity() + 10) {
+            let alloc = TestAllocator::new();
+            let v = SoVec::<String, TestAllocator>::with_capacity(i, alloc);
+
+            assert_eq!(0, v.len());
+            assert!(i <= v.capacity());
+        }
+    }
+
+    #[test]
+    fn try_with_capacity() {
+        let alloc = TestAllocator::new();
+        alloc.set_failing(true);
+
+        for i in 0..(StackBuffer::<String>::capacity() + 10) {
+            let result = SoVec::<String, &TestAllocator>::try_with_capacity(i, &alloc);
+            if i <= StackBuffer::<String>::capacity() {
+                assert!(result.unwrap().is_using_stack());
+            } else {
+                assert!(matches!(result, Err(TryReserveError::AllocError { .. })));
+            }
+        }
+        assert_eq!(0, alloc.alloc_count());
+
+        alloc.set_failing(false);
+        let v = SoVec::<String, &TestAllocator>::try_with_capacity(100, &alloc).unwrap();
+        assert!(100 <= v.capacity());
+        assert_eq!(1, alloc.alloc_count());
+
+        let result = SoVec::<String, &TestAllocator>::try_with_capacity(usize::MAX, &alloc);
+        assert!(matches!(result, Err(TryReserveError::CapacityOverflow)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Allocating memory size is too large.")]
+    fn with_capacity_overflow() {
+        let max_capacity = isize::MAX as usize / core::mem::size_of::<u64>();
+        SoVec::<u64, std::alloc::System>::with_capacity(max_capacity + 1, std::alloc::System);
+    }
+
+    #[test]
+    fn reserve_exact() {
+        for i in 0..(StackBuffer::<u8>::capacity() + 10) {
+            let alloc = TestAllocator::new();
+            let mut v = SoVec::<u8, TestAllocator>::with_capacity(i, alloc);
+
+            for j in 0..(StackBuffer::<u8>::capacity() + 10) {
+                v.reserve_exact(j);
+                assert_eq!(0, v.len());
+                assert!(i <= v.capacity());
+                assert!(j <= v.capacity());
+            }
+        }
+
+        for i in 0..(StackBuffer::<String>::capacity() + 10) {
+            let alloc = TestAllocator::new();
+            let mut v = SoVec::<String, TestAllocator>::with_capacity(i, alloc);
+
+            for j in 0..(StackBuffer::<String>::capacity() + 10) {
+                v.reserve_exact(j);
+                assert_eq!(0, v.len());
+                assert!(i <= v.capacity());
+                assert!(j <= v.capacity());
+            }
+        }
+    }
+
+    #[test]
+    fn push() {
+        {
+            let origin: Vec<u8> = (0..=u8::MAX).collect();
+
+            let mut v = SoVec::<u8, TestAllocator>::default();
+            let init_capacity = v.capacity();
+
+            for i in 0..init_capacity {
+                unsafe { v.push(i as u8) };
+                assert_eq!(&origin[0..=i], v.as_ref());
+            }
+
+            for i in init_capacity..=(u8::MAX as usize) {
+                unsafe {
+                    v.reserve_exact(1);
+                    v.push(i as u8);
+                }
+                assert_eq!(&origin[0..=i], v.as_ref());
+            }
+        }
+        {
+            let origin: Vec<String> = (0..=u8::MAX).map(|i| i.to_string()).collect();
+
+            let mut v = SoVec::<String, TestAllocator>::default();
+            let init_capacity = v.capacity();
+
+            for i in 0..init_capacity {
+                unsafe { v.push(i.to_string()) };
+                assert_eq!(&origin[0..=i], v.as_ref());
+            }
+
+            for i in init_capacity..=3 * init_capacity {
+                unsafe {
+                    v.reserve_exact(1);
+                    v.push(i.to_string());
+                }
+                assert_eq!(&origin[0..=i], v.as_ref());
+            }
+        }
+    }
+
+    #[test]
+    fn pop() {
+        {
+            let alloc = TestAllocator::new();
+            let mut v = SoVec::<u8, TestAllocator>::with_capacity((u8::MAX as usize) + 1, alloc);
+            assert_eq!(None, v.pop());
+
+            for i in 0..=u8::MAX {
+                unsafe {
+                    v.push(i);
+                }
+            }
+
+            for i in (0..=u8::MAX).rev() {
+                assert_eq!(Some(i), v.pop());
+            }
+
+            assert_eq!(None, v.pop());
+        }
+
+        {
+            let alloc = TestAllocator::new();
+            let mut v =
+                SoVec::<String, TestAllocator>::with_capacity((u8::MAX as usize) + 1, alloc);
+            assert_eq!(None, v.pop());
+
+            for i in 0..=u8::MAX {
+                unsafe {
+                    v.push(i.to_string());
+                }
+            }
+
+            for i in (0..=u8::MAX).rev() {
+                assert_eq!(Some(i.to_string()), v.pop());
+            }
+
+            assert_eq!(None, v.pop());
+        }
+    }
+
+    #[cfg(feature = "spill-observer")]
+    #[test]
+    fn spill_observer() {
+        use std::sync::{Arc, Mutex};
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        {
+            let log = log.clone();
+            v.set_spill_observer(move |capacity| log.lock().unwrap().push(capacity));
+        }
+
+        v.extend(0..StackBuffer::<u8>::capacity() as u8);
+        assert!(log.lock().unwrap().is_empty());
+
+        v.push_grow(0);
+        assert_eq!(vec![v.capacity()], *log.lock().unwrap());
+
+        // Reallocation does not call the observer.
+        v.reserve_exact(1000);
+        v.extend(0..100);
+        assert_eq!(1, log.lock().unwrap().len());
+
+        // Spills again after moving back to the stack buffer.
+        v.clear();
+        v.shrink_to_fit();
+        v.reserve_exact(100);
+        assert_eq!(2, log.lock().unwrap().len());
+        assert_eq!(100, log.lock().unwrap()[1]);
+
+        let _ = v.into_iter().into_allocator();
+        assert_eq!(1, Arc::strong_count(&log));
+    }
+
+    #[test]
+    #[cfg(feature = "spill-observer")]
+    fn spill_observer_dismantle() {
+        use std::sync::{Arc, Mutex};
+
+        for len in 0..=StackBuffer::<u8>::capacity() {
+            let log = Arc::new(Mutex::new(Vec::new()));
+            let mut v = SoVec::<u8, TestAllocator>::default();
+            {
+                let log = log.clone();
+                v.set_spill_observer(move |capacity| log.lock().unwrap().push(capacity));
+            }
+            v.extend(0..len as u8);
+
+            // Moving the elements to the heap to dismantle is not a spill.
+            let (handle, alloc) = v.dismantle();
+            assert!(log.lock().unwrap().is_empty());
+            assert_eq!(1, Arc::strong_count(&log));
+
+            if let Some(handle) = handle {
+                let v = unsafe { SoVec::from_heap_handle(handle, alloc) };
+                assert!(v.iter().copied().eq(0..len as u8));
+            }
+        }
+    }
+
+    #[test]
+    fn zero_sized_type_across_max_len() {
+        // `StackBuffer` could hold any number of ZST elements, but the length is stored in `u8` .
+        const MAX_LEN: usize = StackBuffer::<()>::capacity();
+        assert_eq!(u8::MAX as usize - 1, MAX_LEN);
+
+        for len in (MAX_LEN - 2)..(MAX_LEN + 3) {
+            let alloc = TestAllocator::new();
+
+            let mut v = SoVec::<(), &TestAllocator>::new(&alloc);
+            v.extend((0..len).map(|_| ()));
+            assert_eq!(len, v.len());
+            assert_eq!(MAX_LEN < len, v.spilled());
+
+            let mut v = SoVec::<(), &TestAllocator>::new(&alloc);
+            v.extend((0..len).map(|_| ()).filter(|_| true));
+            assert_eq!(len, v.len());
+            assert_eq!(MAX_LEN < len, v.spilled());
+
+            let mut v = SoVec::<(), &TestAllocator>::new(&alloc);
+            v.resize(len, ());
+            assert_eq!(len, v.len());
+            assert_eq!(MAX_LEN < len, v.spilled());
+
+            let mut v = SoVec::<(), &TestAllocator>::new(&alloc);
+            for i in 0..len {
+                v.insert(i / 2, ());
+            }
+            assert_eq!(len, v.len());
+            assert_eq!(MAX_LEN < len, v.spilled());
+
+            // Zero sized type never allocates.
+            assert_eq!(0, alloc.alloc_count());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "max-capacity")]
+    fn max_capacity() {
+        let alloc = TestAllocator::new();
+        let mut v = SoVec::<String, &TestAllocator>::new(&alloc);
+        assert_eq!(usize::MAX, v.max_capacity());
+
+        v.set_max_capacity(10);
+        assert_eq!(10, v.max_capacity());
+        assert!(v.capacity() <= 10);
+
+        for i in 0..10 {
+            v.push_grow(i.to_string());
+        }
+        assert_eq!(10, v.capacity());
+        assert_eq!(Err("10".to_string()), v.push_checked("10".to_string()));
+        assert_eq!(
+            Err(TryReserveError::MaxCapacityExceeded { max_capacity: 10 }),
+            v.try_reserve(1)
+        );
+        assert_eq!(Ok(()), v.try_reserve(0));
+        assert!(v.iter().cloned().eq((0..10).map(|i| i.to_string())));
+
+        let w = v.clone();
+        assert_eq!(10, w.max_capacity());
+
+        // The buffer larger than `max_capacity` is kept.
+        let mut v = SoVec::<u8, &TestAllocator>::with_capacity(100, &alloc);
+        v.set_max_capacity(50);
+        assert_eq!(50, v.capacity());
+        v.extend(0..50);
+        assert_eq!(
+            Err(TryReserveError::MaxCapacityExceeded { max_capacity: 50 }),
+            v.try_reserve_exact(1)
+        );
+
+        v.set_max_capacity(200);
+        v.extend(50..101);
+        assert_eq!(101, v.len());
+    }
+
+    #[test]
+    #[cfg(feature = "max-capacity")]
+    fn max_capacity_reserve_pow2() {
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        v.set_max_capacity(100);
+        v.extend(0..50);
+
+        // The power of 2 (128) is clamped to the max capacity.
+        v.reserve_pow2(50);
+        assert_eq!(100, v.capacity());
+
+        assert_eq!(
+            Err(TryReserveError::MaxCapacityExceeded { max_capacity: 100 }),
+            v.try_reserve(51)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "max-capacity")]
+    fn max_capacity_derived() {
+        for len in 0..(StackBuffer::<String>::capacity() + 10) {
+            let alloc = TestAllocator::new();
+            let mut v = SoVec::<String, &TestAllocator>::new(&alloc);
+            v.extend((0..len).map(|i| i.to_string()));
+            v.set_max_capacity(100);
+
+            assert_eq!(100, v.clone().max_capacity());
+            assert_eq!(100, v.duplicate_with_capacity().max_capacity());
+            assert_eq!(100, v.sub(..).max_capacity());
+            assert_eq!(100, v.to_owned_alloc(TestAllocator::new()).max_capacity());
+            assert_eq!(100, v.split_off(len / 2).max_capacity());
+            assert_eq!(100, v.truncate_return(0).max_capacity());
+            assert_eq!(
+                100,
+                v.split_off_into(0, TestAllocator::new()).max_capacity()
+            );
+
+            v.extend((0..len).map(|i| i.to_string()));
+            let w = v.take_all();
+            assert_eq!(100, v.max_capacity());
+            assert_eq!(100, w.max_capacity());
+
+            let w = w.reallocate_in(TestAllocator::new());
+            assert_eq!(100, w.max_capacity());
+            assert_eq!(len, w.len());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "max-capacity")]
+    #[should_panic(expected = "Requested capacity exceeds the max capacity 1.")]
+    fn max_capacity_push_grow() {
+        let mut v = SoVec::<Box<u8>, std::alloc::System>::default();
+        v.set_max_capacity(1);
+        v.push_grow(Box::new(0));
+        v.push_grow(Box::new(1));
+    }
+
+    #[test]
+    #[cfg(feature = "max-capacity")]
+    #[should_panic(expected = "max_capacity (is 1) should be >= len (is 2)")]
+    fn set_max_capacity_less_than_len() {
+        let mut v = SoVec::<u8, std::alloc::System>::default();
+        v.extend(0..2);
+        v.set_max_capacity(1);
+    }
+
+    #[test]
+    fn zero_sized_type_never_allocates() {
+        const MAX_LEN: usize = StackBuffer::<()>::capacity();
+        let alloc = TestAllocator::new();
+
+        // push_grow
+        {
+            let mut v = SoVec::<(), &TestAllocator>::new(&alloc);
+            for i in 0..(MAX_LEN + 10) {
+                assert_eq!(i, v.len());
+                v.push_grow(());
+            }
+            assert_eq!(usize::MAX, v.capacity());
+
+            v.reserve(usize::MAX - v.len());
+            v.reserve_exact(usize::MAX - v.len());
+            assert_eq!(usize::MAX, v.capacity());
+            assert!(v.try_reserve(usize::MAX - v.len() + 1).is_err());
+        }
+
+        // with_capacity
+        for capacity in 0..(MAX_LEN + 10) {
+            let v = SoVec::<(), &TestAllocator>::with_capacity(capacity, &alloc);
+            assert!(capacity <= v.capacity());
+        }
+
+        // around usize::MAX
+        {
+            let mut v = SoVec::<(), &TestAllocator>::with_capacity(usize::MAX, &alloc);
+            unsafe { v.set_len(usize::MAX - 10) };
+
+            for i in (usize::MAX - 10)..usize::MAX {
+                assert_eq!(i, v.len());
+                assert_eq!(Ok(()), v.push_checked(()));
+            }
+            assert_eq!(usize::MAX, v.len());
+            assert_eq!(Err(()), v.push_checked(()));
+            assert_eq!(
+                Err(TryReserveError::CapacityOverflow),
+                v.try_reserve_exact(1)
+            );
+        }
+
+        assert_eq!(0, alloc.alloc_count());
+    }
+
+    #[test]
+    fn reserve_pow2() {
+        for i in 0..(StackBuffer::<String>::capacity() + 100) {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend((0..i).map(|j| j.to_string()));
+
+            for j in 0..100 {
+                let capacity = v.capacity();
+                v.reserve_pow2(j);
+                assert!(i + j <= v.capacity());
+                if capacity != v.capacity() {
+                    assert!(v.capacity().is_power_of_two());
+                }
+            }
+        }
+
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        v.reserve_pow2(StackBuffer::<u8>::capacity() + 1);
+        assert!(!v.is_using_stack());
+        assert_eq!(
+            (StackBuffer::<u8>::capacity() + 1).next_power_of_two(),
+            v.capacity()
+        );
+    }
+
+    #[test]
+    fn reserve() {
+        for i in 0..(StackBuffer::<u8>::capacity() + 10) {
+            let alloc = TestAllocator::new();
+            let mut v = SoVec::<u8, TestAllocator>::with_capacity(i, alloc);
+
+            for j in 0..(StackBuffer::<u8>::capacity() + 10) {
+                v.reserve(j);
+                assert_eq!(0, v.len());
+                assert!(i <= v.capacity());
+                assert!(j <= v.capacity());
+            }
+        }
+
+        for i in 0..(StackBuffer::<String>::capacity() + 10) {
+            let alloc = TestAllocator::new();
+            let mut v = SoVec::<String, TestAllocator>::with_capacity(i, alloc);
+
+            for j in 0..(StackBuffer::<String>::capacity() + 10) {
+                v.reserve(j);
+                assert_eq!(0, v.len());
+                assert!(i <= v.capacity());
+                assert!(j <= v.capacity());
+            }
+        }
+    }
+
+    #[test]
+    fn extend() {
+        {
+            let origin: Vec<u8> = (0..=u8::MAX).collect();
+
+            for i in 0..origin.len() {
+                let mut v = SoVec::<u8, TestAllocator>::default();
+                v.extend(origin[0..i].iter().cloned());
+                assert_eq!(&origin[0..i], v.as_ref());
+
+                v.extend(origin[i..].iter().cloned());
+                assert_eq!(&origin[..], v.as_ref());
+            }
+        }
+
+        {
+            let origin: Vec<String> = (0..=u8::MAX).map(|i| i.to_string()).collect();
+
+            for i in 0..origin.len() {
+                let mut v = SoVec::<String, TestAllocator>::default();
+                v.extend(origin[0..i].iter().cloned());
+                assert_eq!(&origin[0..i], v.as_ref());
+
+                v.extend(origin[i..].iter().cloned());
+                assert_eq!(&origin[..], v.as_ref());
+            }
+        }
+    }
+
+    #[test]
+    fn clone() {
+        {
+            let origin: Vec<u8> = (0..=u8::MAX).collect();
+
+            for i in 0..origin.len() {
+                let mut v = SoVec::<u8, TestAllocator>::default();
+                v.extend(origin[0..i].iter().cloned());
+
+                let cloned = v.clone();
+                assert_eq!(v.as_ref(), cloned.as_ref());
+            }
+        }
+
+        {
+            let origin: Vec<String> = (0..=u8::MAX).map(|i| i.to_string()).collect();
+
+            for i in 0..origin.len() {
+                let mut v = SoVec::<String, TestAllocator>::default();
+                v.extend(origin[0..i].iter().cloned());
+
+                let cloned = v.clone();
+                assert_eq!(v.as_ref(), cloned.as_ref());
+            }
+        }
+    }
+
+    #[test]
+    fn clone_minimal_capacity() {
+        let alloc = TestAllocator::new();
+
+        let mut v = SoVec::<String, &TestAllocator>::with_capacity(1000, &alloc);
+        v.extend((0..100).map(|i| i.to_string()));
+        let cloned = v.clone();
+        assert_eq!(v, cloned);
+        assert_eq!(100, cloned.capacity());
+
+        v.truncate(StackBuffer::<String>::capacity());
+        let cloned = v.clone();
+        assert_eq!(v, cloned);
+        assert!(!cloned.spilled());
+
+        assert_eq!(2, alloc.alloc_count());
+        assert_eq!(1000, v.duplicate_with_capacity().capacity());
+    }
+
+    #[test]
+    fn deref() {
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        v.extend(0..=u8::MAX);
+
+        let sum = v.iter().map(|&i| i as usize).sum::<usize>();
+        assert_eq!((0..=u8::MAX as usize).sum::<usize>(), sum);
+
+        for elm in v.iter_mut() {
+            *elm = elm.wrapping_add(1);
+        }
+        assert_eq!(1, v[0]);
+        assert_eq!(0, v[u8::MAX as usize]);
+    }
+
+    #[test]
+    fn dedup_keep_last_by() {
+        {
+            let mut v = SoVec::<(u8, char), TestAllocator>::default();
+            v.extend(vec![(1, 'a'), (1, 'b'), (2, 'a'), (2, 'b'), (2, 'c')]);
+
+            v.dedup_keep_last_by(|a, b| a.0 == b.0);
+            assert_eq!(&[(1, 'b'), (2, 'c')], v.as_ref());
+        }
+
+        {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend((0..100).map(|i| (i / 3).to_string()));
+
+            v.dedup_keep_last_by(|a, b| a == b);
+            let expected: Vec<String> = (0..34).map(|i| i.to_string()).collect();
+            assert_eq!(&expected[..], v.as_ref());
+        }
+    }
+
+    #[test]
+    fn reallocate_in() {
+        for i in 0..(StackBuffer::<String>::capacity() + 10) {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend((0..i).map(|j| j.to_string()));
+
+            let w = v.reallocate_in(TestAllocator::new());
+            let expected: Vec<String> = (0..i).map(|j| j.to_string()).collect();
+            assert_eq!(&expected[..], w.as_ref());
+        }
+
+        {
+            let mut v = SoVec::<u8, TestAllocator>::default();
+            v.extend(0..=u8::MAX);
+            assert!(StackBuffer::<u8>::capacity() < v.capacity());
+
+            let w = v.reallocate_in(TestAllocator::new());
+            let expected: Vec<u8> = (0..=u8::MAX).collect();
+            assert_eq!(&expected[..], w.as_ref());
+        }
+    }
+
+    #[test]
+    fn get_disjoint_mut() {
+        {
+            let mut v = SoVec::<u8, TestAllocator>::default();
+            v.extend(0..5);
+
+            {
+                let [a, b] = v.get_disjoint_mut([0, 3]).unwrap();
+                core::mem::swap(a, b);
+                *a += 10;
+            }
+            assert_eq!(&[13, 1, 2, 0, 4], v.as_ref());
+
+            assert!(v.get_disjoint_mut([1, 1]).is_none());
+            assert!(v.get_disjoint_mut([1, 5]).is_none());
+        }
+
+        {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend((0..5).map(|i| i.to_string()));
+
+            {
+                let [a, b] = v.get_disjoint_mut([0, 3]).unwrap();
+                a.push_str(b);
+                b.clear();
+            }
+            assert_eq!(&["03", "1", "2", "", "4"], v.as_ref());
+
+            assert!(v.get_disjoint_mut([1, 1]).is_none());
+            assert!(v.get_disjoint_mut([1, 5]).is_none());
+        }
+    }
+
+    #[test]
+    fn extend_size_hint() {
+        {
+            let origin: Vec<String> = (0..=u8::MAX).map(|i| i.to_string()).collect();
+
+            for i in 0..origin.len() {
+                let mut v = SoVec::<String, TestAllocator>::default();
+                v.extend(origin[0..i].to_vec());
+                assert_eq!(&origin[0..i], v.as_ref());
+                v.extend(origin[i..].to_vec());
+                assert_eq!(&origin[..], v.as_ref());
+            }
+        }
+
+        {
+            let origin: Vec<String> = (0..=u8::MAX).map(|i| i.to_string()).collect();
+            let expected: Vec<String> = (0..=u8::MAX)
+                .filter(|i| i % 3 == 0)
+                .map(|i| i.to_string())
+                .collect();
+
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend(
+                origin
+                    .into_iter()
+                    .filter(|s| s.parse::<u8>().unwrap() % 3 == 0),
+            );
+            assert_eq!(&expected[..], v.as_ref());
+        }
+    }
+
+    #[test]
+    fn with_capacity_usable() {
+        use std::alloc::Layout;
+
+        /// Allocates the memory whose size is rounded up to a multiple of 64 .
+        struct RoundingAllocator;
+
+        impl RoundingAllocator {
+            fn round(layout: Layout) -> Layout {
+                let size = layout.size().div_ceil(64) * 64;
+                Layout::from_size_align(size, layout.align()).unwrap()
+            }
+        }
+
+        unsafe impl GlobalAlloc for RoundingAllocator {
+            unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+                std::alloc::System.alloc(Self::round(layout))
+            }
+
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+                std::alloc::System.dealloc(ptr, Self::round(layout))
+            }
+        }
+
+        unsafe impl UsableSize for RoundingAllocator {
+            fn usable_size(&self, _ptr: *mut u8, layout: Layout) -> usize {
+                Self::round(layout).size()
+            }
+        }
+
+        // The allocator rounds up.
+        {
+            let mut v =
+                SoVec::<u8, RoundingAllocator>::with_capacity_usable(100, RoundingAllocator);
+            assert!(v.spilled());
+            assert_eq!(128, v.capacity());
+            let ptr = unsafe { v.as_ptr() };
+            v.extend(0..128);
+            assert_eq!(ptr, unsafe { v.as_ptr() });
+
+            let v = SoVec::<u64, RoundingAllocator>::with_capacity_usable(33, RoundingAllocator);
+            assert_eq!(40, v.capacity());
+        }
+
+        // The allocator does not tell the usable size.
+        for capacity in 0..(StackBuffer::<String>::capacity() + 10) {
+            let alloc = TestAllocator::new();
+            let v = SoVec::<String, &TestAllocator>::with_capacity_usable(capacity, &alloc);
+            assert_eq!(
+                core::cmp::max(capacity, StackBuffer::<String>::capacity()),
+                v.capacity()
+            );
+        }
+    }
+
+    #[test]
+    fn from_vec() {
+        for len in 0..(StackBuffer::<String>::capacity() + 10) {
+            let vec: Vec<String> = (0..len).map(|i| i.to_string()).collect();
+            let ptr = vec.as_ptr();
+            let capacity = vec.capacity();
+
+            let v =
+                unsafe { SoVec::<String, std::alloc::System>::from_vec(vec, std::alloc::System) };
+            assert_eq!(len, v.len());
+            if 0 < capacity {
+                assert!(v.spilled());
+                assert_eq!(ptr, unsafe { v.as_ptr() });
+                assert_eq!(capacity, v.capacity());
+            }
+            for (i, s) in v.iter().enumerate() {
+                assert_eq!(&i.to_string(), s);
+            }
+        }
+    }
+
+    #[test]
+    fn from_vec_global() {
+        for len in 0..(StackBuffer::<String>::capacity() + 10) {
+            let vec: Vec<String> = (0..len).map(|i| i.to_string()).collect();
+            let ptr = vec.as_ptr();
+            let capacity = vec.capacity();
+
+            let mut v = SoVec::<String, Global>::from(vec);
+            assert_eq!(len, v.len());
+            if 0 < capacity {
+                assert!(v.spilled());
+                assert_eq!(ptr, unsafe { v.as_ptr() });
+                assert_eq!(capacity, v.capacity());
+            }
+            assert!(v.iter().cloned().eq((0..len).map(|i| i.to_string())));
+
+            // The heap memory is reallocated and deallocated by the global allocator.
+            v.extend((len..len + 100).map(|i| i.to_string()));
+            v.truncate(1);
+            v.shrink_to_fit();
+            assert!(v.iter().cloned().eq((0..v.len()).map(|i| i.to_string())));
+        }
+    }
+
+    #[test]
+    fn from_vec_compacting() {
+        // Long vector keeps the heap.
+        {
+            let len = StackBuffer::<u8>::capacity() + 10;
+            let vec: Vec<u8> = (0..len as u8).collect();
+            let ptr = vec.as_ptr();
+
+            let v = unsafe {
+                SoVec::<u8, std::alloc::System>::from_vec_compacting(vec, std::alloc::System)
+            };
+            assert!(v.spilled());
+            assert_eq!(ptr, unsafe { v.as_ptr() });
+            assert_eq!(&(0..len as u8).collect::<Vec<u8>>()[..], v.as_ref());
+        }
+
+        // Short vector is moved into the stack buffer.
+        for len in 0..=StackBuffer::<String>::capacity() {
+            let alloc = TestAllocator::new();
+            let vec: Vec<String> = (0..len).map(|i| i.to_string()).collect();
+
+            let v = unsafe { SoVec::<String, &TestAllocator>::from_vec_compacting(vec, &alloc) };
+            assert!(!v.spilled());
+            let expected: Vec<String> = (0..len).map(|i| i.to_string()).collect();
+            assert_eq!(&expected[..], v.as_ref());
+            assert_eq!(0, alloc.alloc_count());
+        }
+    }
+
+    #[test]
+    fn dismantle() {
+        for len in 0..(StackBuffer::<String>::capacity() + 10) {
+            let alloc = TestAllocator::new();
+            let mut v = SoVec::<String, TestAllocator>::new(alloc);
+            v.extend((0..len).map(|i| i.to_string()));
+            #[cfg(feature = "max-capacity")]
+            v.set_max_capacity(100);
+            let max_capacity = v.max_capacity();
+            let spilled = v.spilled();
+            let ptr = unsafe { v.as_ptr() };
+
+            let (handle, alloc) = v.dismantle();
+            let handle = match handle {
+                None => {
+                    // No heap for the empty vector on the stack.
+                    assert_eq!(0, len);
+                    assert!(!spilled);
+                    assert_eq!(0, alloc.alloc_count());
+                    continue;
+                }
+                Some(handle) => handle,
+            };
+
+            assert_eq!(len, handle.len());
+            assert_eq!(max_capacity, handle.max_capacity());
+            if spilled {
+                assert_eq!(ptr, handle.as_ptr());
+            } else {
+                // The elements on the stack are moved to the heap.
+                assert_eq!(len, handle.capacity());
+                assert_eq!(1, alloc.alloc_count());
+            }
+
+            let capacity = handle.capacity();
+            let v = unsafe { SoVec::from_heap_handle(handle, alloc) };
+            assert_eq!(len, v.len());
+            assert_eq!(max_capacity, v.max_capacity());
+            assert_eq!(capacity, v.capacity());
+            for (i, s) in v.iter().enumerate() {
+                assert_eq!(&i.to_string(), s);
+            }
+        }
+    }
+
+    #[test]
+    fn dismantle_drop() {
+        for len in 0..(StackBuffer::<DropLogger>::capacity() + 3) {
+            let log = Rc::new(RefCell::new(Vec::new()));
+
+            let mut v = SoVec::<DropLogger, TestAllocator>::default();
+            v.extend((0..len).map(|id| DropLogger {
+                id,
+                log: log.clone(),
+            }));
+
+            let (handle, alloc) = v.dismantle();
+            assert!(log.borrow().is_empty());
+            assert_eq!(0 < len, handle.is_some());
+
+            if let Some(handle) = handle {
+                let v = unsafe { SoVec::from_heap_handle(handle, alloc) };
+                drop(v);
+            }
+            let expected: Vec<usize> = (0..len).collect();
+            assert_eq!(expected, *log.borrow());
+        }
+    }
+
+    #[test]
+    fn from_box() {
+        {
+            let boxed: Box<[u8]> = (0..100).collect::<Vec<u8>>().into_boxed_slice();
+            let ptr = boxed.as_ptr();
+
+            let v = unsafe { SoVec::<u8, std::alloc::System>::from_box(boxed, std::alloc::System) };
+            assert!(!v.is_using_stack());
+            assert_eq!(ptr, unsafe { v.as_ptr() });
+            assert_eq!(100, v.capacity());
+            assert!(v.iter().copied().eq(0..100));
+        }
+
+        {
+            let alloc = TestAllocator::new();
+            let boxed = unsafe {
+                let layout = std::alloc::Layout::array::<String>(10).unwrap();
+                let ptr = alloc.alloc(layout) as *mut String;
+                for i in 0..10 {
+                    core::ptr::write(ptr.add(i), i.to_string());
+                }
+                Box::from_raw(core::ptr::slice_from_raw_parts_mut(ptr, 10))
+            };
+
+            let mut v = unsafe { SoVec::<String, &TestAllocator>::from_box(boxed, &alloc) };
+            assert!(v.iter().cloned().eq((0..10).map(|i| i.to_string())));
+
+            v.extend(Some("10".to_string()));
+            assert!(v.iter().cloned().eq((0..11).map(|i| i.to_string())));
+            // (Feature `zeroize` reallocates by `alloc` and `dealloc` .)
+            assert_eq!(2, alloc.alloc_count() + alloc.realloc_count());
+        }
+
+        {
+            let boxed: Box<[String]> = Box::new([]);
+            let v =
+                unsafe { SoVec::<String, TestAllocator>::from_box(boxed, TestAllocator::new()) };
+            assert!(v.is_empty());
+
+            let boxed: Box<[()]> = vec![(); 1000].into_boxed_slice();
+            let v = unsafe { SoVec::<(), TestAllocator>::from_box(boxed, TestAllocator::new()) };
+            assert_eq!(1000, v.len());
+        }
+    }
+
+    #[test]
+    fn extend_exact_size() {
+        // The first allocation fits the exact size.
+        for i in (StackBuffer::<String>::capacity() + 1)..(StackBuffer::<String>::capacity() + 10) {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend((0..i).map(|j| j.to_string()));
+            assert_eq!(i, v.len());
+            assert_eq!(i, v.capacity());
+
+            // The capacity grows geometrically after that.
+            v.extend(Some(i.to_string()));
+            assert_eq!(i + 1, v.len());
+            assert_eq!(2 * i, v.capacity());
+        }
+
+        // Repeated small extends are amortized.
+        {
+            let alloc = TestAllocator::new();
+            let mut v = SoVec::<String, &TestAllocator>::new(&alloc);
+            for i in 0..1000 {
+                v.extend(Some(i.to_string()));
+            }
+            assert_eq!(1000, v.len());
+            assert!(alloc.alloc_count() + alloc.realloc_count() <= 11);
+        }
+    }
+
+    #[test]
+    fn with_ptr() {
+        for len in 0..(StackBuffer::<String>::capacity() + 10) {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend((0..len).map(|i| i.to_string()));
+
+            let sum: usize = v.with_ptr(|ptr| {
+                (0..len)
+                    .map(|i| unsafe { &*ptr.add(i) }.parse::<usize>().unwrap())
+                    .sum()
+            });
+            assert_eq!((0..len).sum::<usize>(), sum);
+
+            v.with_mut_ptr(|ptr| {
+                for i in 0..len {
+                    unsafe { (*ptr.add(i)).push('!') };
+                }
+            });
+            assert!(v.iter().cloned().eq((0..len).map(|i| format!("{}!", i))));
+        }
+    }
+
+    #[test]
+    fn as_ptr_range() {
+        for i in 0..(StackBuffer::<String>::capacity() + 10) {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend((0..i).map(|j| j.to_string()));
+
+            unsafe {
+                let range = v.as_ptr_range();
+                assert_eq!(v.as_ptr(), range.start);
+                assert_eq!(i, range.end.offset_from(range.start) as usize);
+
+                let range = v.as_mut_ptr_range();
+                assert_eq!(v.as_mut_ptr(), range.start);
+                assert_eq!(i, range.end.offset_from(range.start) as usize);
+            }
+        }
+    }
+
+    #[test]
+    fn stack_max_len() {
+        let mut v = SoVec::<(), TestAllocator>::default();
+        assert_eq!(u8::MAX as usize - 1, v.capacity());
+
+        v.extend((0..(u8::MAX - 1)).map(|_| ()));
+        assert_eq!(u8::MAX as usize - 1, v.len());
+        assert!(v.is_using_stack());
+
+        v.extend(Some(()));
+        assert_eq!(u8::MAX as usize, v.len());
+        assert!(!v.is_using_stack());
+
+        assert_eq!(Some(()), v.pop());
+        assert_eq!(u8::MAX as usize - 1, v.len());
+    }
+
+    #[test]
+    fn truncate_drop_order() {
+        for len in 0..(StackBuffer::<DropLogger>::capacity() + 10) {
+            for new_len in 0..=len {
+                let log = Rc::new(RefCell::new(Vec::new()));
+
+                let mut v = SoVec::<DropLogger, TestAllocator>::default();
+                v.extend((0..len).map(|id| DropLogger {
+                    id,
+                    log: log.clone(),
+                }));
+
+                v.truncate(new_len);
+                let expected: Vec<usize> = (new_len..len).collect();
+                assert_eq!(expected, *log.borrow());
+
+                log.borrow_mut().clear();
+                drop(v);
+                let expected: Vec<usize> = (0..new_len).collect();
+                assert_eq!(expected, *log.borrow());
+            }
+        }
+    }
+
+    #[test]
+    fn insert() {
+        for i in 0..(StackBuffer::<String>::capacity() + 10) {
+            for j in 0..=i {
+                let mut expected: Vec<String> = (0..i).map(|k| k.to_string()).collect();
+                let mut v = SoVec::<String, TestAllocator>::default();
+                v.extend(expected.iter().cloned());
+
+                expected.insert(j, "foo".to_string());
+                v.insert(j, "foo".to_string());
+                assert_eq!(&expected[..], v.as_ref());
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "insertion index (is 4) should be <= len (is 3)")]
+    fn insert_out_of_bounds() {
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        v.extend(0..3);
+        v.insert(4, 4);
+    }
+
+    #[test]
+    fn remove() {
+        for i in 0..(StackBuffer::<String>::capacity() + 10) {
+            for j in 0..i {
+                let mut expected: Vec<String> = (0..i).map(|k| k.to_string()).collect();
+                let mut v = SoVec::<String, TestAllocator>::default();
+                v.extend(expected.iter().cloned());
+
+                assert_eq!(expected.remove(j), v.remove(j));
+                assert_eq!(&expected[..], v.as_ref());
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "removal index (is 3) should be < len (is 3)")]
+    fn remove_out_of_bounds() {
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        v.extend(0..3);
+        let _ = v.remove(3);
+    }
+
+    #[test]
+    fn with_capacity_never_allocates_small() {
+        {
+            let alloc = TestAllocator::new();
+            let inline_capacity = SoVec::<u8, &TestAllocator>::new(&alloc).capacity();
+
+            for i in 0..=inline_capacity {
+                let v = SoVec::<u8, &TestAllocator>::with_capacity(i, &alloc);
+                assert_eq!(inline_capacity, v.capacity());
+                assert_eq!(0, alloc.alloc_count());
+            }
+
+            let v = SoVec::<u8, &TestAllocator>::with_capacity(inline_capacity + 1, &alloc);
+            assert!(inline_capacity < v.capacity());
+            assert_eq!(1, alloc.alloc_count());
+        }
+
+        {
+            let alloc = TestAllocator::new();
+            let inline_capacity = SoVec::<String, &TestAllocator>::new(&alloc).capacity();
+
+            for i in 0..=inline_capacity {
+                let v = SoVec::<String, &TestAllocator>::with_capacity(i, &alloc);
+                assert_eq!(inline_capacity, v.capacity());
+                assert_eq!(0, alloc.alloc_count());
+            }
+
+            let v = SoVec::<String, &TestAllocator>::with_capacity(inline_capacity + 1, &alloc);
+            assert!(inline_capacity < v.capacity());
+            assert_eq!(1, alloc.alloc_count());
+        }
+    }
+
+    #[test]
+    fn position() {
+        let mut v = SoVec::<String, TestAllocator>::default();
+        v.extend((0..100).map(|i| (i % 10).to_string()));
+        assert!(!v.is_using_stack());
+
+        assert_eq!(Some(3), v.position(|s| s == "3"));
+        assert_eq!(Some(93), v.rposition(|s| s == "3"));
+        assert_eq!(Some(&"3".to_string()), v.find(|s| s.starts_with('3')));
+
+        assert_eq!(None, v.position(|s| s == "10"));
+        assert_eq!(None, v.rposition(|s| s == "10"));
+        assert_eq!(None, v.find(|s| s == "10"));
+    }
+
+    #[test]
+    fn split() {
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        v.extend([1, 0, 2, 0, 3].iter().copied());
+
+        let parts: Vec<&[u8]> = v.split(|&b| b == 0).collect();
+        assert_eq!(vec![&[1][..], &[2], &[3]], parts);
+
+        let parts: Vec<&[u8]> = v.rsplit(|&b| b == 0).collect();
+        assert_eq!(vec![&[3][..], &[2], &[1]], parts);
+
+        v.extend(Some(0));
+        let parts: Vec<&[u8]> = v.split(|&b| b == 0).collect();
+        assert_eq!(vec![&[1][..], &[2], &[3], &[]], parts);
+    }
+
+    #[test]
+    fn starts_with() {
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        assert!(v.starts_with(b""));
+        assert!(!v.starts_with(b"\x89PNG"));
+
+        v.extend(b"\x89PNG\r\n\x1a\n".iter().copied());
+        v.extend(0..100);
+        v.extend(b"IEND".iter().copied());
+
+        assert!(v.starts_with(b"\x89PNG"));
+        assert!(!v.starts_with(b"GIF8"));
+        assert!(v.ends_with(b"IEND"));
+        assert!(!v.ends_with(b"IEN"));
+        assert!(v.starts_with(v.as_ref()));
+        assert!(v.ends_with(v.as_ref()));
+    }
+
+    #[test]
+    fn is_sorted() {
+        let mut v = SoVec::<String, TestAllocator>::default();
+        assert!(v.is_sorted());
+
+        v.extend(["a", "b", "b", "c"].iter().map(|s| s.to_string()));
+        assert!(v.is_sorted());
+        assert!(v.is_sorted_by(|a, b| a <= b));
+        assert!(!v.is_sorted_by(|a, b| a < b));
+
+        v.extend(Some("a".to_string()));
+        assert!(!v.is_sorted());
+        assert!(!v.is_sorted_by(|a, b| a <= b));
+    }
+
+    #[test]
+    fn par_chunks_mut() {
+        for len in 0..(StackBuffer::<usize>::capacity() + 10) {
+            let mut v = SoVec::<usize, TestAllocator>::default();
+            v.extend(0..len);
+
+            let chunks: Vec<&mut [usize]> = v.par_chunks_mut(3).collect();
+            assert_eq!(3, chunks.len());
+            assert!(chunks
+                .iter()
+                .all(|c| len / 3 <= c.len() && c.len() <= len / 3 + 1));
+
+            let covered: Vec<usize> = chunks.iter().flat_map(|c| c.iter().copied()).collect();
+            assert_eq!((0..len).collect::<Vec<usize>>(), covered);
+        }
+
+        let mut v = SoVec::<usize, TestAllocator>::default();
+        v.extend(0..10);
+        std::thread::scope(|s| {
+            for chunk in v.par_chunks_mut(3) {
+                s.spawn(move || chunk.iter_mut().for_each(|i| *i *= 2));
+            }
+        });
+        assert_eq!(
+            &(0..10).map(|i| i * 2).collect::<Vec<usize>>()[..],
+            v.as_ref()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "the number of the chunks should be > 0")]
+    fn par_chunks_mut_zero() {
+        let mut v = SoVec::<u8, std::alloc::System>::default();
+        let _ = v.par_chunks_mut(0);
+    }
+
+    #[test]
+    fn split_once() {
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        assert_eq!(None, v.split_once(&b'='));
+
+        v.extend(b"k=v".iter().copied());
+        assert_eq!(Some((&b"k"[..], &b"v"[..])), v.split_once(&b'='));
+        assert_eq!(None, v.split_once(&b':'));
+        assert_eq!(Some((&b""[..], &b"=v"[..])), v.split_once(&b'k'));
+        assert_eq!(Some((&b"k="[..], &b""[..])), v.split_once(&b'v'));
+
+        v.extend(b"=w".iter().copied());
+        assert_eq!(Some((&b"k"[..], &b"v=w"[..])), v.split_once(&b'='));
+    }
+
+    #[test]
+    fn chunks_exact() {
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        v.extend(0..10);
+
+        let mut it = v.chunks_exact(4);
+        assert_eq!(Some(&[0, 1, 2, 3][..]), it.next());
+        assert_eq!(Some(&[4, 5, 6, 7][..]), it.next());
+        assert_eq!(None, it.next());
+        assert_eq!(2, it.remainder().len());
+        assert_eq!(&[8, 9], it.remainder());
+
+        let mut it = v.chunks_exact_mut(4);
+        for chunk in &mut it {
+            chunk.reverse();
+        }
+        for elm in it.into_remainder() {
+            *elm = 0;
+        }
+        assert_eq!(&[3, 2, 1, 0, 7, 6, 5, 4, 0, 0], v.as_ref());
+    }
+
+    #[test]
+    #[should_panic]
+    fn chunks_exact_zero() {
+        let v = SoVec::<u8, std::alloc::System>::default();
+        let _ = v.chunks_exact(0);
+    }
+
+    #[test]
+    fn equal_range() {
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        assert_eq!(0..0, v.equal_range(&2));
+
+        v.extend([1, 2, 2, 2, 3].iter().copied());
+        assert_eq!(1..4, v.equal_range(&2));
+        assert_eq!(0..1, v.equal_range(&1));
+        assert_eq!(4..5, v.equal_range(&3));
+        assert_eq!(0..0, v.equal_range(&0));
+        assert_eq!(5..5, v.equal_range(&4));
+
+        for len in 0..(StackBuffer::<String>::capacity() + 10) {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend((0..len).map(|i| (i / 3).to_string()));
+            v.sort();
+
+            for i in 0..len {
+                let x = (i / 3).to_string();
+                let range = v.equal_range(&x);
+                assert!(v[range.clone()].iter().all(|s| *s == x));
+                assert!(v[..range.start].iter().all(|s| *s < x));
+                assert!(v[range.end..].iter().all(|s| x < *s));
+            }
+        }
+    }
+
+    #[test]
+    fn max_min() {
+        let mut v = SoVec::<String, TestAllocator>::default();
+        assert_eq!(None, v.max());
+        assert_eq!(None, v.min());
+
+        v.extend(
+            ["3", "1", "4", "1", "5", "9", "2", "6"]
+                .iter()
+                .map(|s| s.to_string()),
+        );
+        assert_eq!("9", v.max().unwrap());
+        assert_eq!("1", v.min().unwrap());
+        assert!(core::ptr::eq(&v[1], v.min().unwrap()));
+    }
+
+    #[test]
+    fn refill() {
+        let alloc = TestAllocator::new();
+        let mut v = SoVec::<String, &TestAllocator>::with_capacity(100, &alloc);
+        assert_eq!(1, alloc.alloc_count());
+
+        v.refill((0..100).map(|i| i.to_string()));
+        let expected: Vec<String> = (0..100).map(|i| i.to_string()).collect();
+        assert_eq!(&expected[..], v.as_ref());
+
+        v.refill((0..50).map(|i| (2 * i).to_string()));
+        let expected: Vec<String> = (0..50).map(|i| (2 * i).to_string()).collect();
+        assert_eq!(&expected[..], v.as_ref());
+
+        assert_eq!(1, alloc.alloc_count());
+        assert_eq!(0, alloc.realloc_count());
+    }
+
+    #[test]
+    fn to_owned_alloc() {
+        fn build(n: usize) -> SoVec<String, TestAllocator> {
+            let alloc = TestAllocator::new();
+            let mut v = SoVec::<String, &TestAllocator>::new(&alloc);
+            v.extend((0..n).map(|i| i.to_string()));
+            v.to_owned_alloc(TestAllocator::new())
+        }
+
+        for i in 0..(StackBuffer::<String>::capacity() + 10) {
+            let v = build(i);
+            let expected: Vec<String> = (0..i).map(|j| j.to_string()).collect();
+            assert_eq!(&expected[..], v.as_ref());
+        }
+    }
+
+    #[test]
+    fn push_pop_front() {
+        let alloc = TestAllocator::new();
+        let mut v = SoVec::<String, &TestAllocator>::new(&alloc);
+        assert_eq!(None, v.pop_front());
+
+        for i in 0..(StackBuffer::<String>::capacity() + 10) {
+            v.push_front(i.to_string());
+            assert_eq!(i + 1, v.len());
+            assert_eq!(StackBuffer::<String>::capacity() <= i, v.spilled());
+        }
+        let expected: Vec<String> = (0..(StackBuffer::<String>::capacity() + 10))
+            .rev()
+            .map(|i| i.to_string())
+            .collect();
+        assert_eq!(&expected[..], v.as_ref());
+
+        for i in (0..(StackBuffer::<String>::capacity() + 10)).rev() {
+            assert_eq!(Some(i.to_string()), v.pop_front());
+        }
+        assert_eq!(None, v.pop_front());
+        assert!(v.is_empty());
+    }
+
+    #[test]
+    fn retain_swap() {
+        for len in 0..(StackBuffer::<String>::capacity() + 10) {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend((0..len).map(|i| i.to_string()));
+
+            v.retain_swap(|s| s.parse::<usize>().unwrap() % 3 != 0);
+
+            let mut kept: Vec<usize> = v.iter().map(|s| s.parse().unwrap()).collect();
+            kept.sort_unstable();
+            let expected: Vec<usize> = (0..len).filter(|i| i % 3 != 0).collect();
+            assert_eq!(expected, kept);
+        }
+    }
+
+    #[test]
+    fn retain_rev() {
+        for len in 0..(StackBuffer::<String>::capacity() + 10) {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend((0..len).map(|i| i.to_string()));
+
+            let mut visited = Vec::new();
+            v.retain_rev(|s| {
+                let i = s.parse::<usize>().unwrap();
+                visited.push(i);
+                i % 3 != 0
+            });
+
+            let expected: Vec<usize> = (0..len).rev().collect();
+            assert_eq!(expected, visited);
+
+            let expected: Vec<String> = (0..len)
+                .filter(|i| i % 3 != 0)
+                .map(|i| i.to_string())
+                .collect();
+            assert_eq!(&expected[..], v.as_ref());
+        }
+    }
+
+    #[test]
+    fn retain_rev_panic() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let mut v = SoVec::<String, TestAllocator>::default();
+        v.extend((0..10).map(|i| i.to_string()));
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            v.retain_rev(|s| match s.parse::<usize>().unwrap() {
+                5 => panic!(),
+                i => i % 2 == 0,
+            })
+        }));
+        assert!(result.is_err());
+
+        let expected = ["0", "1", "2", "3", "4", "5", "6", "8"];
+        assert_eq!(&expected[..], v.as_ref());
+    }
+
+    #[test]
+    fn unique() {
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        v.extend([3, 1, 3, 2, 1].iter().copied());
+        v.unique();
+        assert_eq!(&[3, 1, 2], v.as_ref());
+
+        for len in 0..(StackBuffer::<String>::capacity() + 10) {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend((0..len).map(|i| ((i * 7) % 5).to_string()));
+            v.unique();
+
+            let expected: Vec<String> = [0, 2, 4, 1, 3]
+                .iter()
+                .take(len)
+                .map(|i| i.to_string())
+                .collect();
+            assert_eq!(&expected[..], v.as_ref());
+        }
+    }
+
+    #[test]
+    fn dedup_drop_order() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut v = SoVec::<DropLogger, TestAllocator>::default();
+
+        // The keys are [a, a, b, b, b, c, a, a]
+        let keys = [0, 0, 1, 1, 1, 2, 0, 0];
+        v.extend((0..keys.len()).map(|id| DropLogger {
+            id,
+            log: log.clone(),
+        }));
+
+        v.dedup_by_key(|logger| keys[logger.id]);
+        assert_eq!(vec![1, 3, 4, 7], *log.borrow());
+        assert!(v
+            .iter()
+            .map(|logger| logger.id)
+            .eq([0, 2, 5, 6].iter().copied()));
+
+        drop(v);
+        assert_eq!(vec![1, 3, 4, 7, 0, 2, 5, 6], *log.borrow());
+    }
+
+    #[test]
+    fn dedup_never_allocates() {
+        let alloc = TestAllocator::new();
+        let mut v = SoVec::<String, &TestAllocator>::new(&alloc);
+
+        let fill = |v: &mut SoVec<String, &TestAllocator>| {
+            v.clear();
+            v.extend((0..100).map(|i| (i / 3).to_string()));
+        };
+
+        v.reserve(100);
+        fill(&mut v);
+        assert!(v.spilled());
+        let counts = || {
+            (
+                alloc.alloc_count(),
+                alloc.realloc_count(),
+                alloc.dealloc_count(),
+            )
+        };
+        let before = counts();
+
+        v.dedup();
+        assert_eq!(34, v.len());
+        assert_eq!(before, counts());
+
+        fill(&mut v);
+        v.dedup_by(|a, b| a == b);
+        assert_eq!(34, v.len());
+        assert_eq!(before, counts());
+
+        fill(&mut v);
+        v.dedup_by_key(|s| s.clone());
+        assert_eq!(34, v.len());
+        assert_eq!(before, counts());
+
+        fill(&mut v);
+        v.dedup_keep_last_by(|a, b| a == b);
+        assert_eq!(34, v.len());
+        assert_eq!(before, counts());
+    }
+
+    #[test]
+    fn dedup_count() {
+        for len in 0..(StackBuffer::<String>::capacity() + 10) {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend((0..len).map(|i| (i / 3).to_string()));
+            let capacity = v.capacity();
+
+            let removed = v.dedup_count();
+            assert_eq!(len - v.len(), removed);
+            assert_eq!(len.div_ceil(3), v.len());
+            assert_eq!(capacity, v.capacity());
+            assert!(v.iter().cloned().eq((0..v.len()).map(|i| i.to_string())));
+        }
+    }
+
+    #[test]
+    fn dedup() {
+        {
+            let mut v = SoVec::<u8, TestAllocator>::default();
+            v.extend(vec![1, 1, 2, 3, 3, 3, 1, 4, 4]);
+
+            v.dedup();
+            assert_eq!(&[1, 2, 3, 1, 4], v.as_ref());
+        }
+
+        {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend((0..100).map(|i| (i / 3).to_string()));
+
+            v.dedup();
+            let expected: Vec<String> = (0..34).map(|i| i.to_string()).collect();
+            assert_eq!(&expected[..], v.as_ref());
+        }
+    }
+
+    #[test]
+    fn dedup_by_key() {
+        let mut v = SoVec::<(u8, char), TestAllocator>::default();
+        v.extend(vec![(1, 'a'), (1, 'b'), (2, 'a'), (2, 'b'), (2, 'c')]);
+
+        v.dedup_by_key(|elm| elm.0);
+        assert_eq!(&[(1, 'a'), (2, 'a')], v.as_ref());
+    }
+
+    #[test]
+    fn sort_and_dedup() {
+        {
+            let mut v = SoVec::<u8, TestAllocator>::default();
+            v.extend(vec![3, 1, 2, 1, 3]);
+
+            v.sort_and_dedup();
+            assert_eq!(&[1, 2, 3], v.as_ref());
+        }
+
+        {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend((0..100).rev().map(|i| (i % 10).to_string()));
+
+            v.sort_and_dedup();
+            let expected: Vec<String> = (0..10).map(|i| i.to_string()).collect();
+            assert_eq!(&expected[..], v.as_ref());
+        }
+    }
+
+    #[test]
+    fn append_iter() {
+        let mut v = SoVec::<String, TestAllocator>::default();
+        assert_eq!(0, v.append_iter(None));
+        assert_eq!(3, v.append_iter((0..3).map(|i| i.to_string())));
+
+        let added = v.append_iter((3..100).filter(|i| i % 2 == 0).map(|i| i.to_string()));
+        assert_eq!(48, added);
+        assert_eq!(51, v.len());
+    }
+
+    #[test]
+    fn extend_move() {
+        let alloc = TestAllocator::new();
+        let others_alloc = TestAllocator::new();
+
+        let mut v = SoVec::<String, &TestAllocator>::new(&alloc);
+        v.push_grow("x".to_string());
+
+        let others = [0..1, 1..30, 30..35].iter().cloned().map(|range| {
+            let mut w = SoVec::<String, &TestAllocator>::new(&others_alloc);
+            w.extend(range.map(|i| i.to_string()));
+            w
+        });
+        v.extend_move(others);
+
+        let expected: Vec<String> = Some("x".to_string())
+            .into_iter()
+            .chain((0..35).map(|i| i.to_string()))
+            .collect();
+        assert_eq!(&expected[..], v.as_ref());
+
+        // Each source buffer is released.
+        assert_eq!(others_alloc.alloc_count(), others_alloc.dealloc_count());
+
+        v.extend_move(None::<SoVec<String, &TestAllocator>>);
+        assert_eq!(36, v.len());
+    }
+
+    #[test]
+    fn extend_from_slice_unchecked() {
+        let origin: Vec<u8> = (0..100).collect();
+
+        for i in 0..(StackBuffer::<u8>::capacity() + 10) {
+            let mut v = SoVec::<u8, TestAllocator>::default();
+            v.extend(0..i as u8);
+            v.reserve(origin.len());
+
+            unsafe { v.extend_from_slice_unchecked(&origin) };
+            assert!(v.iter().copied().eq((0..i as u8).chain(0..100)));
+        }
+    }
+
+    #[test]
+    fn extend_from_slices() {
+        let inline_capacity = StackBuffer::<u8>::capacity();
+        let origin: Vec<u8> = (0..=u8::MAX).collect();
+        let a = &origin[0..3];
+        let b = &origin[3..inline_capacity];
+        let c = &origin[inline_capacity..(inline_capacity + 5)];
+
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        v.extend_from_slices(&[a, b]);
+        assert_eq!(&origin[0..inline_capacity], v.as_ref());
+        assert!(v.is_using_stack());
+
+        v.extend_from_slices(&[]);
+        assert_eq!(&origin[0..inline_capacity], v.as_ref());
+
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        v.extend_from_slices(&[a, b, c]);
+        assert_eq!(&origin[0..(inline_capacity + 5)], v.as_ref());
+        assert!(!v.is_using_stack());
+    }
+
+    #[test]
+    fn duplicate_with_capacity() {
+        for i in 0..(StackBuffer::<String>::capacity() + 10) {
+            let mut v =
+                SoVec::<String, TestAllocator>::with_capacity(i + 100, TestAllocator::new());
+            v.extend((0..i).map(|j| j.to_string()));
+
+            let w = v.duplicate_with_capacity();
+            assert_eq!(v.as_ref(), w.as_ref());
+            assert_eq!(v.capacity(), w.capacity());
+        }
+    }
+
+    #[test]
+    fn split_first_mut() {
+        let mut v = SoVec::<usize, TestAllocator>::default();
+        assert!(v.split_first_mut().is_none());
+        assert!(v.split_last_mut().is_none());
+
+        v.extend(1..=10);
+
+        {
+            let (first, rest) = v.split_first_mut().unwrap();
+            for elm in rest.iter() {
+                *first += *elm;
+            }
+        }
+        assert_eq!(55, v[0]);
+
+        {
+            let (last, rest) = v.split_last_mut().unwrap();
+            for elm in rest.iter_mut() {
+                *elm += *last;
+            }
+        }
+        assert_eq!(65, v[0]);
+        assert_eq!(19, v[8]);
+        assert_eq!(10, v[9]);
+    }
+
+    #[test]
+    fn index_unchecked() {
+        let mut v = SoVec::<String, TestAllocator>::default();
+        v.extend((0..100).map(|i| i.to_string()));
+
+        for i in 0..100 {
+            unsafe {
+                assert_eq!(&i.to_string(), v.index_unchecked(i));
+                v.index_unchecked_mut(i).push('!');
+            }
+        }
+
+        let expected: Vec<String> = (0..100).map(|i| format!("{}!", i)).collect();
+        assert_eq!(&expected[..], v.as_slice());
+    }
+
+    #[test]
+    fn over_aligned() {
+        #[repr(align(64))]
+        #[derive(Debug, PartialEq, Clone)]
+        struct Aligned(String);
+
+        let origin: Vec<Aligned> = (0..100).map(|i| Aligned(i.to_string())).collect();
+
+        let alloc = TestAllocator::new();
+        let mut v = SoVec::<Aligned, &TestAllocator>::new(&alloc);
+        assert_eq!(&origin[0..0], v.as_ref());
+        assert_eq!(0, unsafe { v.as_ptr() } as usize % 64);
+
+        for i in 0..origin.len() {
+            v.extend(Some(origin[i].clone()));
+            assert!(!v.is_using_stack());
+            assert_eq!(0, unsafe { v.as_ptr() } as usize % 64);
+            assert_eq!(&origin[0..=i], v.as_ref());
+        }
+
+        v.shrink_to_fit();
+        assert_eq!(&origin[..], v.as_ref());
+    }
+
+    #[test]
+    fn shrink_to_fit() {
+        let alloc = TestAllocator::new();
+        let mut v = SoVec::<String, &TestAllocator>::with_capacity(200, &alloc);
+        v.extend((0..100).map(|i| i.to_string()));
+
+        // (Feature `zeroize` reallocates by `alloc` and `dealloc` .)
+        v.shrink_to_fit();
+        assert_eq!(100, v.capacity());
+        assert_eq!(2, alloc.alloc_count() + alloc.realloc_count());
+
+        // The capacity already equals to the length.
+        v.shrink_to_fit();
+        assert_eq!(100, v.capacity());
+        assert_eq!(2, alloc.alloc_count() + alloc.realloc_count());
+        assert!(v.iter().cloned().eq((0..100).map(|i| i.to_string())));
+
+        // Moves back to the stack buffer.
+        for len in 0..=StackBuffer::<String>::capacity() {
+            let alloc = TestAllocator::new();
+            let mut v = SoVec::<String, &TestAllocator>::with_capacity(200, &alloc);
+            v.extend((0..len).map(|i| i.to_string()));
+
+            v.shrink_to_fit();
+            assert!(!v.spilled());
+            assert_eq!(1, alloc.dealloc_count());
+            assert!(v.iter().cloned().eq((0..len).map(|i| i.to_string())));
+
+            v.extend((len..100).map(|i| i.to_string()));
+            assert!(v.iter().cloned().eq((0..100).map(|i| i.to_string())));
+        }
+    }
+
+    #[test]
+    fn reserve_growth() {
+        const N: usize = 10000;
+
+        let alloc = TestAllocator::new();
+        let mut v = SoVec::<u8, &TestAllocator>::new(&alloc);
+        let mut capacity = v.capacity();
+
+        for i in 0..N {
+            v.reserve(1);
+            unsafe { v.push(i as u8) };
+
+            // The capacity never decreases.
+            assert!(capacity <= v.capacity());
+            capacity = v.capacity();
+
+            // Not so much memory is wasted.
+            if !v.is_using_stack() {
+                assert!(v.capacity() <= 2 * v.len());
+            }
+        }
+
+        // Reallocation occurs O(log N) times.
+        let log_n = (usize::BITS - N.leading_zeros()) as usize;
+        assert!(alloc.alloc_count() + alloc.realloc_count() <= log_n);
+        #[cfg(not(feature = "zeroize"))]
+        assert_eq!(1, alloc.alloc_count());
+    }
+
+    #[test]
+    fn split_off() {
+        let len = StackBuffer::<String>::capacity() + 10;
+        let origin: Vec<String> = (0..len).map(|i| i.to_string()).collect();
+
+        for at in 0..=len {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend(origin.iter().cloned());
+            let capacity = v.capacity();
+
+            let w = v.split_off(at);
+            assert_eq!(&origin[..at], v.as_ref());
+            assert_eq!(&origin[at..], w.as_ref());
+            assert_eq!(capacity, v.capacity());
+        }
+    }
+
+    #[test]
+    fn truncate_drop_panic() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        /// Logs `id` when dropped, and panics if `id` equals to `bomb` .
+        struct Bomb {
+            id: usize,
+            bomb: usize,
+            log: Rc<RefCell<Vec<usize>>>,
+        }
+
+        impl Drop for Bomb {
+            fn drop(&mut self) {
+                self.log.borrow_mut().push(self.id);
+                if self.id == self.bomb {
+                    panic!("Boom!");
+                }
+            }
+        }
+
+        let len = StackBuffer::<Bomb>::capacity() + 10;
+        for new_len in 0..len {
+            for bomb in new_len..len {
+                let log = Rc::new(RefCell::new(Vec::new()));
+                let mut v = SoVec::<Bomb, TestAllocator>::default();
+                v.extend((0..len).map(|id| Bomb {
+                    id,
+                    bomb,
+                    log: log.clone(),
+                }));
+
+                let result = catch_unwind(AssertUnwindSafe(|| v.truncate(new_len)));
+                assert!(result.is_err());
+
+                // The length is set before dropping, and the elements after the panicking one are
+                // dropped as well.
+                assert_eq!(new_len, v.len());
+                let expected: Vec<usize> = (new_len..len).collect();
+                assert_eq!(expected, *log.borrow());
+
+                log.borrow_mut().clear();
+                drop(v);
+                let expected: Vec<usize> = (0..new_len).collect();
+                assert_eq!(expected, *log.borrow());
+            }
+        }
+    }
+
+    #[test]
+    fn truncate_return() {
+        let len = StackBuffer::<String>::capacity() + 10;
+        let origin: Vec<String> = (0..len).map(|i| i.to_string()).collect();
+
+        for new_len in 0..(len + 3) {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend(origin.iter().cloned());
+
+            let w = v.truncate_return(new_len);
+            let new_len = core::cmp::min(new_len, len);
+            assert_eq!(&origin[..new_len], v.as_ref());
+            assert_eq!(&origin[new_len..], w.as_ref());
+        }
+    }
+
+    #[test]
+    fn split_off_into() {
+        let len = StackBuffer::<String>::capacity() + 10;
+        let origin: Vec<String> = (0..len).map(|i| i.to_string()).collect();
+
+        let alloc0 = TestAllocator::new();
+        let alloc1 = TestAllocator::new();
+
+        let mut v = SoVec::<String, &TestAllocator>::from(&alloc0);
+        v.extend(origin.iter().cloned());
+        assert_eq!(1, alloc0.alloc_count());
+
+        let mut w = v.split_off_into(1, &alloc1);
+        assert_eq!(&origin[..1], v.as_ref());
+        assert_eq!(&origin[1..], w.as_ref());
+        assert_eq!(1, alloc0.alloc_count());
+        assert_eq!(1, alloc1.alloc_count());
+
+        let x = w.split_off_into(len - 2, std::alloc::System);
+        assert_eq!(&origin[1..len - 1], w.as_ref());
+        assert_eq!(&origin[len - 1..], x.as_ref());
+    }
+
+    #[test]
+    fn to_vec() {
+        for len in 0..(StackBuffer::<String>::capacity() + 10) {
+            let alloc = TestAllocator::new();
+            let mut v = SoVec::<String, &TestAllocator>::new(&alloc);
+            v.extend((0..len).map(|i| i.to_string()));
+
+            let w: Vec<String> = v.to_vec();
+            assert_eq!(v.as_ref(), &w[..]);
+            assert_eq!(len, v.len());
+
+            drop(w);
+            let expected: Vec<String> = (0..len).map(|i| i.to_string()).collect();
+            assert_eq!(&expected[..], v.as_ref());
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn into_arc_slice() {
+        for len in 0..(StackBuffer::<String>::capacity() + 10) {
+            let alloc = TestAllocator::new();
+            let mut v = SoVec::<String, &TestAllocator>::new(&alloc);
+            v.extend((0..len).map(|i| i.to_string()));
+
+            let arc = v.into_arc_slice();
+            assert_eq!(alloc.alloc_count(), alloc.dealloc_count());
+
+            let cloned = arc.clone();
+            let handle =
+                std::thread::spawn(move || cloned.iter().cloned().collect::<Vec<String>>());
+            let expected: Vec<String> = (0..len).map(|i| i.to_string()).collect();
+            assert_eq!(expected, handle.join().unwrap());
+            assert_eq!(&expected[..], &arc[..]);
+        }
+    }
+
+    #[test]
+    fn split_off_vec() {
+        let len = StackBuffer::<String>::capacity() + 10;
+        let origin: Vec<String> = (0..len).map(|i| i.to_string()).collect();
+
+        for at in 0..=len {
+            let alloc = TestAllocator::new();
+            let mut v = SoVec::<String, &TestAllocator>::new(&alloc);
+            v.extend(origin.iter().cloned());
+            assert!(v.spilled());
+
+            let w: Vec<String> = v.split_off_vec(at);
+            assert_eq!(&origin[..at], v.as_ref());
+            assert_eq!(&origin[at..], &w[..]);
+            assert_eq!(1, alloc.alloc_count());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "`at` split index (is 2) should be <= len (is 1)")]
+    fn split_off_out_of_bounds() {
+        let mut v = SoVec::<u8, std::alloc::System>::default();
+        v.extend(Some(0));
+        let _ = v.split_off(2);
+    }
+
+    #[test]
+    fn sub() {
+        // u8
+        {
+            let alloc = TestAllocator::new();
+            let mut v = SoVec::<u8, &TestAllocator>::new(&alloc);
+            v.extend(0..100);
+            assert!(v.spilled());
+
+            let w = v.sub(1..4);
+            assert_eq!(&[1, 2, 3], w.as_ref());
+            assert!(!w.spilled());
+            assert_eq!(1, alloc.alloc_count());
+        }
+
+        // String
+        {
+            let alloc = TestAllocator::new();
+            let mut v = SoVec::<String, &TestAllocator>::new(&alloc);
+            v.extend((0..100).map(|i| i.to_string()));
+
+            assert_eq!(v, v.sub(..));
+            assert!(v.sub(100..).is_empty());
+            assert_eq!(&v[50..=60], v.sub(50..=60).as_ref());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "range end index 101 out of range for slice of length 100")]
+    fn sub_out_of_bounds() {
+        let mut v = SoVec::<u8, std::alloc::System>::default();
+        v.extend(0..100);
+        let _ = v.sub(1..101);
+    }
+
+    #[test]
+    fn take_all() {
+        for i in 0..(StackBuffer::<String>::capacity() + 10) {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend((0..i).map(|j| j.to_string()));
+
+            let w = v.take_all();
+            let expected: Vec<String> = (0..i).map(|j| j.to_string()).collect();
+            assert!(v.is_empty());
+            assert!(v.is_using_stack());
+            assert_eq!(&expected[..], w.as_ref());
+
+            v.extend(Some("foo".to_string()));
+            assert_eq!(&["foo"], v.as_ref());
+        }
+    }
+
+    #[test]
+    fn eq() {
+        for i in 0..(StackBuffer::<String>::capacity() + 10) {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend((0..i).map(|j| j.to_string()));
+
+            let alloc = TestAllocator::new();
+            let mut w = SoVec::<String, &TestAllocator>::with_capacity(100, &alloc);
+            w.extend((0..i).map(|j| j.to_string()));
+
+            assert!(v == w);
+            assert!(w == v);
+
+            w.extend(Some("foo".to_string()));
+            assert!(v != w);
+            assert!(w != v);
+        }
+    }
+
+    #[test]
+    fn push_checked() {
+        {
+            let mut v = SoVec::<u8, TestAllocator>::default();
+            let capacity = v.capacity();
+
+            for i in 0..capacity {
+                assert_eq!(Ok(()), v.push_checked(i as u8));
+            }
+            assert_eq!(Err(0), v.push_checked(0));
+            assert_eq!(capacity, v.len());
+            assert_eq!(capacity, v.capacity());
+        }
+
+        {
+            let mut v = SoVec::<String, TestAllocator>::with_capacity(100, TestAllocator::new());
+            let capacity = v.capacity();
+
+            for i in 0..capacity {
+                assert_eq!(Ok(()), v.push_checked(i.to_string()));
+            }
+            assert_eq!(Err("foo".to_string()), v.push_checked("foo".to_string()));
+            assert_eq!(capacity, v.len());
+            assert_eq!(capacity, v.capacity());
+        }
+    }
+
+    #[test]
+    fn push_grow() {
+        let origin: Vec<String> = (0..=u8::MAX).map(|i| i.to_string()).collect();
+        let mut v = SoVec::<String, TestAllocator>::default();
+
+        for i in 0..origin.len() {
+            v.push_grow(origin[i].clone());
+            assert_eq!(&origin[0..=i], v.as_ref());
+        }
+    }
+
+    #[test]
+    fn push_n() {
+        let mut v = SoVec::<String, TestAllocator>::default();
+        let mut iter = (0..10).map(|i| i.to_string());
+
+        v.push_n(&mut iter, 5);
+        assert!(v.iter().cloned().eq((0..5).map(|i| i.to_string())));
+        assert_eq!(Some("5".to_string()), iter.next());
+
+        v.push_n((5..200).map(|i| i.to_string()), 95);
+        assert!(v.iter().cloned().eq((0..100).map(|i| i.to_string())));
+        assert_eq!(100, v.capacity());
+    }
+
+    #[test]
+    #[should_panic(expected = "iter yielded only 3 elements (n is 5)")]
+    fn push_n_short() {
+        let mut v = SoVec::<u8, std::alloc::System>::default();
+        v.push_n(0..3, 5);
+    }
+
+    #[test]
+    fn sort_unstable_by_key() {
+        struct Foo {
+            key: usize,
+            value: String,
+        }
+
+        let mut v = SoVec::<Foo, TestAllocator>::default();
+        v.extend((0..100).map(|i| Foo {
+            key: (i * 37) % 100,
+            value: i.to_string(),
+        }));
+
+        v.sort_unstable_by_key(|foo| foo.key);
+        for (i, foo) in v.iter().enumerate() {
+            assert_eq!(i, foo.key);
+            assert_eq!(i, (foo.value.parse::<usize>().unwrap() * 37) % 100);
+        }
+    }
+
+    #[test]
+    fn swap_with_slice() {
+        let mut v = SoVec::<String, TestAllocator>::default();
+        v.extend((0..3).map(|i| i.to_string()));
+        let mut other = ["a".to_string(), "b".to_string(), "c".to_string()];
+
+        v.swap_with_slice(&mut other);
+        assert_eq!(&["a", "b", "c"], v.as_ref());
+        assert_eq!(["0", "1", "2"], other);
+    }
+
+    #[test]
+    #[should_panic]
+    fn swap_with_slice_different_len() {
+        let mut v = SoVec::<u8, std::alloc::System>::default();
+        v.extend(0..3);
+        v.swap_with_slice(&mut [0; 4]);
+    }
+
+    #[test]
+    fn copy_to_slice() {
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        v.extend(0..100);
+        assert!(v.spilled());
+
+        let mut dst = [u8::MAX; 128];
+        v.copy_to_slice(&mut dst);
+        assert_eq!(v.as_ref(), &dst[..100]);
+        assert!(dst[100..].iter().all(|&b| b == u8::MAX));
+    }
+
+    #[test]
+    #[should_panic(expected = "dst.len() (is 99) should be >= len (is 100)")]
+    fn copy_to_slice_short() {
+        let mut v = SoVec::<u8, std::alloc::System>::default();
+        v.extend(0..100);
+        v.copy_to_slice(&mut [0; 99]);
+    }
+
+    #[test]
+    fn shrinking_keeps_capacity() {
+        let alloc = TestAllocator::new();
+        let mut v = SoVec::<String, &TestAllocator>::new(&alloc);
+        assert!(!v.spilled());
+
+        v.extend((0..100).map(|i| i.to_string()));
+        assert!(v.spilled());
+        let capacity = v.capacity();
+
+        v.truncate(1);
+        assert_eq!(capacity, v.capacity());
+        assert!(v.spilled());
+
+        v.resize(100, String::new());
+        v.resize(0, String::new());
+        assert_eq!(capacity, v.capacity());
+        assert!(v.spilled());
+
+        v.clear();
+        assert_eq!(capacity, v.capacity());
+        assert!(v.spilled());
+
+        assert_eq!(1, alloc.alloc_count());
+        assert_eq!(0, alloc.realloc_count());
+    }
+
+    #[test]
+    fn resize() {
+        for i in 0..(StackBuffer::<String>::capacity() + 10) {
+            for j in 0..(StackBuffer::<String>::capacity() + 10) {
+                let mut expected: Vec<String> = (0..i).map(|k| k.to_string()).collect();
+                let mut v = SoVec::<String, TestAllocator>::default();
+                v.extend(expected.iter().cloned());
+
+                expected.resize(j, "foo".to_string());
+                v.resize(j, "foo".to_string());
+                assert_eq!(&expected[..], v.as_ref());
+            }
+        }
+    }
+
+    #[test]
+    fn try_resize() {
+        for i in 0..(StackBuffer::<String>::capacity() + 10) {
+            let alloc = TestAllocator::new();
+            let mut v = SoVec::<String, &TestAllocator>::new(&alloc);
+            v.extend((0..i).map(|j| j.to_string()));
+            let expected: Vec<String> = (0..i).map(|j| j.to_string()).collect();
+            let capacity = v.capacity();
+
+            alloc.set_failing(true);
+            assert!(matches!(
+                v.try_resize(capacity + 1, "foo".to_string()),
+                Err(TryReserveError::AllocError { .. })
+            ));
+            assert_eq!(&expected[..], v.as_ref());
+            assert_eq!(capacity, v.capacity());
+
+            assert_eq!(
+                Err(TryReserveError::CapacityOverflow),
+                v.try_resize(usize::MAX, "foo".to_string())
+            );
+            assert_eq!(&expected[..], v.as_ref());
+            assert_eq!(capacity, v.capacity());
+
+            alloc.set_failing(false);
+            assert_eq!(Ok(()), v.try_resize(capacity + 1, "foo".to_string()));
+            assert_eq!(capacity + 1, v.len());
+        }
+    }
+
+    #[test]
+    fn try_resize_clone_panic() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        /// Panics when cloned more than `budget` times in total.
+        struct Bomb {
+            budget: Rc<core::cell::Cell<usize>>,
+        }
+
+        impl Clone for Bomb {
+            fn clone(&self) -> Self {
+                if self.budget.get() == 0 {
+                    panic!("Boom!");
+                }
+                self.budget.set(self.budget.get() - 1);
+                Self {
+                    budget: self.budget.clone(),
+                }
+            }
+        }
+
+        let budget = Rc::new(core::cell::Cell::new(3));
+        let mut v = SoVec::<Bomb, TestAllocator>::default();
+        let result = catch_unwind(AssertUnwindSafe(|| v.try_resize(100, Bomb { budget })));
+        assert!(result.is_err());
+
+        // The elements cloned before the panic are kept.
+        assert_eq!(3, v.len());
+    }
+
+    #[test]
+    fn fill_zeroed() {
+        for i in 0..(StackBuffer::<u8>::capacity() + 10) {
+            let mut v = SoVec::<u8, TestAllocator>::default();
+            v.extend((1..=i).map(|j| j as u8));
+            let capacity = v.capacity();
+
+            v.fill_zeroed();
+            assert_eq!(i, v.len());
+            assert_eq!(capacity, v.capacity());
+            assert!(v.iter().all(|&b| b == 0));
+        }
+    }
+
+    #[test]
+    fn as_str() {
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        assert_eq!(Ok(""), v.as_str());
+
+        let s = "ねこ".repeat(StackBuffer::<u8>::capacity());
+        v.extend(s.bytes());
+        assert!(!v.is_using_stack());
+        assert_eq!(Ok(s.as_str()), v.as_str());
+        assert_eq!(s, unsafe { v.as_str_unchecked() });
+
+        // Cut in the middle of the multi-byte character.
+        v.truncate(s.len() - 1);
+        let err = v.as_str().unwrap_err();
+        assert_eq!(s.len() - "こ".len(), err.valid_up_to());
+
+        v.clear();
+        v.extend(b"ab\xffcd".iter().copied());
+        assert_eq!(2, v.as_str().unwrap_err().valid_up_to());
+    }
+
+    /// Checks the memory is zeroed on dealloc, and panics on realloc.
+    #[cfg(feature = "zeroize")]
+    struct ZeroCheckAllocator;
+
+    #[cfg(feature = "zeroize")]
+    unsafe impl GlobalAlloc for ZeroCheckAllocator {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            std::alloc::System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            let bytes = core::slice::from_raw_parts(ptr, layout.size());
+            assert!(bytes.iter().all(|&b| b == 0));
+            std::alloc::System.dealloc(ptr, layout);
+        }
+
+        unsafe fn realloc(
+            &self,
+            _ptr: *mut u8,
+            _layout: std::alloc::Layout,
+            _new_size: usize,
+        ) -> *mut u8 {
+            panic!("realloc may leave the old memory as it is.");
+        }
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn zeroize() {
+        use core::mem::MaybeUninit;
+
+        // Heap
+        {
+            let mut v = SoVec::<u8, ZeroCheckAllocator>::new(ZeroCheckAllocator);
+            v.extend(1..=u8::MAX);
+            assert!(!v.is_using_stack());
+        }
+
+        // Stack
+        {
+            let mut v = MaybeUninit::new(SoVec::<u8, ZeroCheckAllocator>::new(ZeroCheckAllocator));
+            unsafe {
+                let v = &mut *v.as_mut_ptr();
+                v.extend(1..=(StackBuffer::<u8>::capacity() as u8));
+                assert!(v.is_using_stack());
+            }
+
+            let (ptr, capacity) = unsafe {
+                let v = &*v.as_ptr();
+                (v.as_ptr(), v.capacity())
+            };
+
+            unsafe {
+                core::ptr::drop_in_place(v.as_mut_ptr());
+                let bytes = core::slice::from_raw_parts(ptr, capacity);
+                assert!(bytes.iter().all(|&b| b == 0));
+            }
+        }
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn zeroize_realloc() {
+        let origin: Vec<u8> = (1..=100).collect();
+
+        let mut v = SoVec::<u8, ZeroCheckAllocator>::with_capacity(100, ZeroCheckAllocator);
+        v.extend(origin.iter().copied());
+
+        // Grow
+        v.reserve_exact(100);
+        assert_eq!(200, v.capacity());
+        assert_eq!(&origin[..], v.as_ref());
+
+        // Shrink
+        v.truncate(50);
+        v.shrink_to_fit();
+        assert!(v.spilled());
+        assert_eq!(50, v.capacity());
+        assert_eq!(&origin[..50], v.as_ref());
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn zeroize_to_stack() {
+        let mut v = SoVec::<u8, ZeroCheckAllocator>::new(ZeroCheckAllocator);
+        v.extend(1..=100);
+        assert!(v.spilled());
+
+        v.truncate(3);
+        v.shrink_to_fit();
+        assert!(!v.spilled());
+        assert_eq!(&[1, 2, 3], v.as_ref());
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn zeroize_spill() {
+        let capacity = StackBuffer::<u8>::capacity();
+
+        let mut v = SoVec::<u8, ZeroCheckAllocator>::new(ZeroCheckAllocator);
+        v.resize(capacity, 0xaa);
+        let ptr = unsafe { v.as_ptr() };
+
+        v.reserve(capacity + 1);
+        assert!(v.spilled());
+        assert!(v.iter().all(|&b| b == 0xaa));
+
+        // `HeapBuffer` is placed on the front of the stack buffer, and the rest must be zeroed.
+        let start = core::mem::size_of::<HeapBuffer<u8>>();
+        let bytes = unsafe { core::slice::from_raw_parts(ptr.add(start), capacity - start) };
+        assert!(bytes.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn from_exact_iter() {
+        for len in 0..(StackBuffer::<String>::capacity() + 10) {
+            let alloc = TestAllocator::new();
+            let origin: Vec<String> = (0..len).map(|i| i.to_string()).collect();
+
+            let v = SoVec::from_exact_iter(origin.clone(), &alloc);
+            assert_eq!(&origin[..], v.as_ref());
+            assert_eq!(len <= StackBuffer::<String>::capacity(), !v.spilled());
+            assert_eq!(v.spilled() as usize, alloc.alloc_count());
+            assert_eq!(0, alloc.realloc_count());
+        }
+    }
+
+    #[test]
+    fn from_fn() {
+        let v = SoVec::from_fn(5, |i| i * i, TestAllocator::new());
+        assert_eq!(&[0, 1, 4, 9, 16], v.as_ref());
+
+        for n in 0..(StackBuffer::<String>::capacity() + 10) {
+            let alloc = TestAllocator::new();
+            let v = SoVec::from_fn(n, |i| i.to_string(), &alloc);
+            assert!(v.iter().cloned().eq((0..n).map(|i| i.to_string())));
+            assert_eq!(n <= StackBuffer::<String>::capacity(), !v.spilled());
+            assert_eq!(v.spilled() as usize, alloc.alloc_count());
+        }
+    }
+
+    #[test]
+    fn from_fn_panic() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let alloc = TestAllocator::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            SoVec::from_fn(
+                10,
+                |id| {
+                    if id == 5 {
+                        panic!("Boom!");
+                    }
+                    DropLogger {
+                        id,
+                        log: log.clone(),
+                    }
+                },
+                &alloc,
+            )
+        }));
+        assert!(result.is_err());
+
+        let expected: Vec<usize> = (0..5).collect();
+        assert_eq!(expected, *log.borrow());
+    }
+
+    #[test]
+    fn from_iter_in() {
+        for i in 0..(StackBuffer::<String>::capacity() + 10) {
+            let alloc = TestAllocator::new();
+            let v = SoVec::from_iter_in((0..i).map(|j| j.to_string()), &alloc);
+            let expected: Vec<String> = (0..i).map(|j| j.to_string()).collect();
+            assert_eq!(&expected[..], v.as_ref());
+
+            let w: SoVec<String, TestAllocator> = (0..i).map(|j| j.to_string()).collect();
+            assert!(v == w);
+        }
+    }
+
+    #[test]
+    fn fill_spare() {
+        let mut v = SoVec::<usize, TestAllocator>::with_capacity(100, TestAllocator::new());
+        v.fill_spare(10, |i| i * i);
+        v.fill_spare(0, |_| unreachable!());
+        v.fill_spare(90, |i| i * i);
+
+        let expected: Vec<usize> = (0..100).map(|i| i * i).collect();
+        assert_eq!(&expected[..], v.as_ref());
+    }
+
+    #[test]
+    fn spare_capacity_mut() {
+        let mut v = SoVec::<String, TestAllocator>::with_capacity(100, TestAllocator::new());
+        v.extend(Some("0".to_string()));
+        assert_eq!(v.capacity() - 1, v.spare_capacity_mut().len());
+
+        let spare = v.spare_capacity_mut();
+        spare[0] = MaybeUninit::new("1".to_string());
+        spare[1] = MaybeUninit::new("2".to_string());
+        unsafe { v.set_len(3) };
+
+        assert_eq!(&["0", "1", "2"], v.as_ref());
+    }
+
+    #[test]
+    fn split_at_spare_mut() {
+        let mut v = SoVec::<String, TestAllocator>::with_capacity(100, TestAllocator::new());
+        v.extend(Some("0".to_string()));
+        let capacity = v.capacity();
+
+        let (init, spare) = v.split_at_spare_mut();
+        assert_eq!(1, init.len());
+        assert_eq!(capacity - 1, spare.len());
+        spare[0] = MaybeUninit::new(init[0].clone() + "1");
+        spare[1] = MaybeUninit::new(init[0].clone() + "2");
+        init[0].push('0');
+        unsafe { v.set_len(3) };
+
+        assert_eq!(&["00", "01", "02"], v.as_ref());
     }
-}
 
-impl<T, A> AsMut<[T]> for SoVec<T, A>
-where
-    A: GlobalAlloc,
-{
-    fn as_mut(&mut self) -> &mut [T] {
-        unsafe { core::slice::from_raw_parts_mut(self.as_mut_ptr(), self.len()) }
+    #[test]
+    #[should_panic(expected = "count (is 101) should be <= spare capacity (is 100)")]
+    fn fill_spare_overflow() {
+        let mut v = SoVec::<usize, TestAllocator>::with_capacity(100, TestAllocator::new());
+        v.fill_spare(101, |i| i);
     }
-}
 
-impl<T, A> Drop for SoVec<T, A>
-where
-    A: GlobalAlloc,
-{
-    fn drop(&mut self) {
-        self.clear();
+    #[test]
+    fn fill_spare_panic() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
 
-        if !self.is_using_stack() {
-            let alloc = &self.alloc as *const A;
-            unsafe { self.as_mut_heap().pre_drop(&*alloc) };
-        }
-    }
-}
+        let mut v = SoVec::<String, TestAllocator>::with_capacity(100, TestAllocator::new());
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            v.fill_spare(10, |i| {
+                if i == 5 {
+                    panic!("Boom!");
+                }
+                i.to_string()
+            })
+        }));
+        assert!(result.is_err());
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::allocator::TestAllocator;
+        let expected: Vec<String> = (0..5).map(|i| i.to_string()).collect();
+        assert_eq!(&expected[..], v.as_ref());
+    }
 
     #[test]
-    fn from() {
+    fn spill_panic() {
+        use std::alloc::Layout;
+        use std::cell::Cell;
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        /// Panics on `alloc` and `realloc` while `panicking` is true.
+        struct PanicAllocator<'a> {
+            inner: &'a TestAllocator,
+            panicking: &'a Cell<bool>,
+        }
+
+        unsafe impl GlobalAlloc for PanicAllocator<'_> {
+            unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+                if self.panicking.get() {
+                    panic!("Boom!");
+                }
+                self.inner.alloc(layout)
+            }
+
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+                self.inner.dealloc(ptr, layout)
+            }
+
+            unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+                if self.panicking.get() {
+                    panic!("Boom!");
+                }
+                self.inner.realloc(ptr, layout, new_size)
+            }
+        }
+
+        let capacity = StackBuffer::<DropLogger>::capacity();
+        let logger = |id, log: &Rc<RefCell<Vec<usize>>>| DropLogger {
+            id,
+            log: log.clone(),
+        };
+
+        // Panic while spilling.
         {
             let alloc = TestAllocator::new();
-            let v = SoVec::<u8, TestAllocator>::from(alloc);
+            let panicking = Cell::new(true);
+            let log = Rc::new(RefCell::new(Vec::new()));
+            {
+                let mut v = SoVec::new(PanicAllocator {
+                    inner: &alloc,
+                    panicking: &panicking,
+                });
+                v.extend((0..capacity).map(|i| logger(i, &log)));
 
-            assert_eq!(0, v.len());
+                let result = catch_unwind(AssertUnwindSafe(|| v.reserve(1)));
+                assert!(result.is_err());
+                assert!(v.is_using_stack());
+                assert_eq!(capacity, v.len());
+                assert!(log.borrow().is_empty());
+            }
+            let expected: Vec<usize> = (0..capacity).collect();
+            assert_eq!(expected, *log.borrow());
+            assert_eq!(0, alloc.alloc_count());
         }
 
+        // Panic while reallocating the heap.
         {
             let alloc = TestAllocator::new();
-            let v = SoVec::<String, TestAllocator>::from(alloc);
+            let panicking = Cell::new(false);
+            let log = Rc::new(RefCell::new(Vec::new()));
+            {
+                let mut v = SoVec::new(PanicAllocator {
+                    inner: &alloc,
+                    panicking: &panicking,
+                });
+                v.extend((0..=capacity).map(|i| logger(i, &log)));
+                assert!(!v.is_using_stack());
 
-            assert_eq!(0, v.len());
+                panicking.set(true);
+                let result = catch_unwind(AssertUnwindSafe(|| v.reserve(v.capacity() + 1)));
+                assert!(result.is_err());
+                assert_eq!(capacity + 1, v.len());
+                assert!(log.borrow().is_empty());
+            }
+            let expected: Vec<usize> = (0..=capacity).collect();
+            assert_eq!(expected, *log.borrow());
+            assert_eq!(1, alloc.alloc_count());
         }
 
+        // Panic just after spilling.
         {
             let alloc = TestAllocator::new();
-            let v = SoVec::<[u8; 3], TestAllocator>::from(alloc);
+            let log = Rc::new(RefCell::new(Vec::new()));
+            {
+                let mut v = SoVec::<DropLogger, &TestAllocator>::new(&alloc);
+                let result = catch_unwind(AssertUnwindSafe(|| {
+                    // `filter` hides the size from `extend` so that the spill happens after
+                    // the stack buffer is filled.
+                    v.extend((0..capacity + 10).filter(|_| true).map(|i| {
+                        if i == capacity + 1 {
+                            panic!("Boom!");
+                        }
+                        logger(i, &log)
+                    }))
+                }));
+                assert!(result.is_err());
+                assert!(!v.is_using_stack());
+                assert_eq!(capacity + 1, v.len());
+                assert!(log.borrow().is_empty());
+            }
+            let expected: Vec<usize> = (0..=capacity).collect();
+            assert_eq!(expected, *log.borrow());
+            assert_eq!(1, alloc.alloc_count());
+        }
+    }
 
-            assert_eq!(0, v.len());
+    #[test]
+    fn flatten() {
+        let alloc = TestAllocator::new();
+        let inner_alloc = TestAllocator::new();
+
+        let lens = [3, 0, StackBuffer::<String>::capacity() + 10, 1];
+        let mut v = SoVec::<SoVec<String, &TestAllocator>, &TestAllocator>::new(&alloc);
+        let mut start = 0;
+        for &len in lens.iter() {
+            let inner =
+                SoVec::from_iter_in((start..start + len).map(|i| i.to_string()), &inner_alloc);
+            v.push_grow(inner);
+            start += len;
         }
+        assert!(v.iter().any(|inner| inner.spilled()));
+        assert!(v.iter().any(|inner| !inner.spilled()));
+
+        let w = v.flatten();
+        assert!(w.iter().cloned().eq((0..start).map(|i| i.to_string())));
+        assert_eq!(start, w.capacity());
+        assert_eq!(inner_alloc.alloc_count(), inner_alloc.dealloc_count());
     }
 
     #[test]
-    fn with_capacity() {
-        for i in 0..(StackBuffer::<u8>::capacity() + 10) {
-            let alloc = TestAllocator::new();
-            let v = SoVec::<u8, TestAllocator>::with_capacity(i, alloc);
+    fn bytes_eq() {
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        v.extend(0..=u8::MAX);
 
-            assert_eq!(0, v.len());
-            assert!(i <= v.capacity());
+        let alloc = TestAllocator::new();
+        let mut w = SoVec::<u8, &TestAllocator>::new(&alloc);
+        unsafe {
+            assert!(!v.bytes_eq(&w));
+
+            w.extend(0..u8::MAX);
+            assert!(!v.bytes_eq(&w));
+
+            w.extend(Some(u8::MAX));
+            assert!(v.bytes_eq(&w));
+            assert!(w.bytes_eq(&v));
+
+            w[100] = 0;
+            assert!(!v.bytes_eq(&w));
         }
 
-        for i in 0..(StackBuffer::<String>::capacity() + 10) {
-            let alloc = TestAllocator::new();
-            let v = SoVec::<String, TestAllocator>::with_capacity(i, alloc);
+        // The result differs from `==` .
+        let mut v = SoVec::<f32, TestAllocator>::default();
+        v.extend([f32::NAN, 0.0]);
+        let mut w = v.clone();
+        assert!(v != w);
+        assert!(unsafe { v.bytes_eq(&w) });
 
-            assert_eq!(0, v.len());
-            assert!(i <= v.capacity());
+        w[0] = -0.0;
+        w[1] = -0.0;
+        v[0] = 0.0;
+        assert!(v == w);
+        assert!(!unsafe { v.bytes_eq(&w) });
+
+        // `PartialEq` is not required.
+        #[derive(Clone, Copy)]
+        #[allow(dead_code)]
+        struct Pixel(u16, u16);
+
+        for len in 0..(StackBuffer::<Pixel>::capacity() + 10) {
+            let mut v = SoVec::<Pixel, TestAllocator>::default();
+            v.extend((0..len).map(|i| Pixel(i as u16, 0)));
+            let mut w = v.clone();
+            assert!(unsafe { v.bytes_eq(&w) });
+
+            w.push_grow(Pixel(0, 0));
+            assert!(!unsafe { v.bytes_eq(&w) });
+
+            if 0 < len {
+                w.truncate(len);
+                w[len - 1].1 = 1;
+                assert!(!unsafe { v.bytes_eq(&w) });
+            }
         }
     }
 
     #[test]
-    fn reserve_exact() {
-        for i in 0..(StackBuffer::<u8>::capacity() + 10) {
-            let alloc = TestAllocator::new();
-            let mut v = SoVec::<u8, TestAllocator>::with_capacity(i, alloc);
+    fn apply() {
+        for len in 0..(StackBuffer::<usize>::capacity() + 10) {
+            let mut v = SoVec::<usize, TestAllocator>::default();
+            v.extend(0..len);
 
-            for j in 0..(StackBuffer::<u8>::capacity() + 10) {
-                v.reserve_exact(j);
-                assert_eq!(0, v.len());
-                assert!(i <= v.capacity());
-                assert!(j <= v.capacity());
-            }
+            v.apply(|i| *i += 1);
+            assert!(v.iter().copied().eq(1..=len));
         }
+    }
 
-        for i in 0..(StackBuffer::<String>::capacity() + 10) {
+    #[test]
+    fn for_each_consume() {
+        for len in 0..(StackBuffer::<usize>::capacity() + 10) {
             let alloc = TestAllocator::new();
-            let mut v = SoVec::<String, TestAllocator>::with_capacity(i, alloc);
+            let mut v = SoVec::<usize, &TestAllocator>::new(&alloc);
+            v.extend(0..len);
 
-            for j in 0..(StackBuffer::<String>::capacity() + 10) {
-                v.reserve_exact(j);
-                assert_eq!(0, v.len());
-                assert!(i <= v.capacity());
-                assert!(j <= v.capacity());
-            }
+            let mut sum = 0;
+            v.for_each_consume(|i| sum += i);
+            assert_eq!((0..len).sum::<usize>(), sum);
         }
+
+        let alloc = TestAllocator::new();
+        let mut v = SoVec::<String, &TestAllocator>::new(&alloc);
+        v.extend((0..100).map(|i| i.to_string()));
+        let mut consumed = Vec::new();
+        v.for_each_consume(|s| consumed.push(s));
+        assert!(consumed.into_iter().eq((0..100).map(|i| i.to_string())));
     }
 
     #[test]
-    fn push() {
-        {
-            let origin: Vec<u8> = (0..=u8::MAX).collect();
+    fn for_each_consume_panic() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
 
-            let mut v = SoVec::<u8, TestAllocator>::default();
-            let init_capacity = v.capacity();
+        let alloc = TestAllocator::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
 
-            for i in 0..init_capacity {
-                unsafe { v.push(i as u8) };
-                assert_eq!(&origin[0..=i], v.as_ref());
-            }
+        let mut v = SoVec::<DropLogger, &TestAllocator>::new(&alloc);
+        v.extend((0..10).map(|id| DropLogger {
+            id,
+            log: log.clone(),
+        }));
 
-            for i in init_capacity..=(u8::MAX as usize) {
-                unsafe {
-                    v.reserve_exact(1);
-                    v.push(i as u8);
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            v.for_each_consume(|logger| {
+                if logger.id == 5 {
+                    panic!("Boom!");
                 }
-                assert_eq!(&origin[0..=i], v.as_ref());
-            }
-        }
-        {
-            let origin: Vec<String> = (0..=u8::MAX).map(|i| i.to_string()).collect();
+            })
+        }));
+        assert!(result.is_err());
+
+        let expected: Vec<usize> = (0..10).collect();
+        assert_eq!(expected, *log.borrow());
+    }
 
+    #[test]
+    fn reset() {
+        for len in 0..(StackBuffer::<String>::capacity() + 10) {
             let mut v = SoVec::<String, TestAllocator>::default();
-            let init_capacity = v.capacity();
+            v.extend((0..len).map(|i| i.to_string()));
+            let capacity = v.capacity();
 
-            for i in 0..init_capacity {
-                unsafe { v.push(i.to_string()) };
-                assert_eq!(&origin[0..=i], v.as_ref());
-            }
+            v.reset();
+            assert_eq!(len, v.len());
+            assert_eq!(capacity, v.capacity());
+            assert!(v.iter().all(|s| s.is_empty()));
+        }
+    }
 
-            for i in init_capacity..=3 * init_capacity {
-                unsafe {
-                    v.reserve_exact(1);
-                    v.push(i.to_string());
+    #[test]
+    fn map_in_place() {
+        let mut v = SoVec::<usize, TestAllocator>::default();
+        v.extend(0..100);
+        assert!(!v.is_using_stack());
+
+        v.map_in_place(|i| 2 * i);
+        let expected: Vec<usize> = (0..100).map(|i| 2 * i).collect();
+        assert_eq!(&expected[..], v.as_ref());
+    }
+
+    #[test]
+    fn map_in_place_panic() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut v = SoVec::<DropLogger, TestAllocator>::default();
+        v.extend((0..10).map(|id| DropLogger {
+            id,
+            log: log.clone(),
+        }));
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            v.map_in_place(|elm| {
+                if elm.id == 5 {
+                    panic!("Boom!");
                 }
-                assert_eq!(&origin[0..=i], v.as_ref());
-            }
+                DropLogger {
+                    id: elm.id + 100,
+                    log: elm.log.clone(),
+                }
+            })
+        }));
+        assert!(result.is_err());
+
+        // The replaced elements and the element passed to `f` when panicked.
+        let mut expected: Vec<usize> = (0..=5).collect();
+        assert_eq!(expected, *log.borrow());
+
+        drop(v);
+        expected.extend(100..105);
+        expected.extend(6..10);
+        assert_eq!(expected, *log.borrow());
+    }
+
+    #[test]
+    fn clear_from() {
+        let len = StackBuffer::<String>::capacity() + 10;
+        let origin: Vec<String> = (0..len).map(|i| i.to_string()).collect();
+
+        for index in 0..=len {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend(origin.iter().cloned());
+            assert!(!v.is_using_stack());
+
+            v.clear_from(index);
+            assert_eq!(&origin[..index], v.as_ref());
         }
     }
 
     #[test]
-    fn pop() {
+    #[should_panic(expected = "index (is 2) should be <= len (is 1)")]
+    fn clear_from_out_of_bounds() {
+        let mut v = SoVec::<u8, std::alloc::System>::default();
+        v.extend(Some(0));
+        v.clear_from(2);
+    }
+
+    #[test]
+    fn clear_until() {
+        let len = StackBuffer::<String>::capacity() + 10;
+        let origin: Vec<String> = (0..len).map(|i| i.to_string()).collect();
+
+        for index in 0..=len {
+            let mut v = SoVec::<String, TestAllocator>::default();
+            v.extend(origin.iter().cloned());
+            assert!(!v.is_using_stack());
+
+            v.clear_until(index);
+            assert_eq!(&origin[index..], v.as_ref());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "index (is 2) should be <= len (is 1)")]
+    fn clear_until_out_of_bounds() {
+        let mut v = SoVec::<u8, std::alloc::System>::default();
+        v.extend(Some(0));
+        v.clear_until(2);
+    }
+
+    #[test]
+    fn keep_range() {
         {
-            let alloc = TestAllocator::new();
-            let mut v = SoVec::<u8, TestAllocator>::with_capacity((u8::MAX as usize) + 1, alloc);
-            assert_eq!(None, v.pop());
+            let mut v = SoVec::<u8, TestAllocator>::default();
+            v.extend(0..7);
+            v.keep_range(2..5);
+            assert_eq!(&[2, 3, 4], v.as_ref());
+        }
 
-            for i in 0..=u8::MAX {
-                unsafe {
-                    v.push(i);
+        for len in 0..(StackBuffer::<String>::capacity() + 10) {
+            for start in 0..=len {
+                for end in start..=len {
+                    let origin: Vec<String> = (0..len).map(|i| i.to_string()).collect();
+                    let mut v = SoVec::<String, TestAllocator>::default();
+                    v.extend(origin.iter().cloned());
+
+                    v.keep_range(start..end);
+                    assert_eq!(&origin[start..end], v.as_ref());
                 }
             }
+        }
 
-            for i in (0..=u8::MAX).rev() {
-                assert_eq!(Some(i), v.pop());
-            }
+        {
+            let mut v = SoVec::<u8, TestAllocator>::default();
+            v.extend(0..7);
+            v.keep_range(..=3);
+            assert_eq!(&[0, 1, 2, 3], v.as_ref());
+            v.keep_range(1..);
+            assert_eq!(&[1, 2, 3], v.as_ref());
+            v.keep_range(..);
+            assert_eq!(&[1, 2, 3], v.as_ref());
+        }
+    }
 
-            assert_eq!(None, v.pop());
+    #[test]
+    #[should_panic(expected = "range end index 8 out of range for slice of length 7")]
+    fn keep_range_out_of_bounds() {
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        v.extend(0..7);
+        v.keep_range(2..8);
+    }
+
+    #[test]
+    #[should_panic(expected = "slice index starts at 3 but ends at 2")]
+    fn keep_range_reversed() {
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        v.extend(0..7);
+        #[allow(clippy::reversed_empty_ranges)]
+        v.keep_range(3..2);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn check_state() {
+        let mut v = SoVec::<u8, TestAllocator>::default();
+        v.check_state(true);
+
+        v.reserve(v.capacity() + 1);
+        v.check_state(false);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "SoVec is expected to use StackBuffer")]
+    fn check_state_heap() {
+        // `System` is used instead of `TestAllocator` , because the drop of `TestAllocator`
+        // panics again while unwinding.
+        let mut v = SoVec::<u8, std::alloc::System>::default();
+        v.reserve(v.capacity() + 1);
+        v.check_state(true);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "SoVec is expected to use HeapBuffer")]
+    fn check_state_not_disabled() {
+        let mut v = SoVec::<u8, std::alloc::System>::default();
+
+        // Bug: Overwrites `self.buffer` with `HeapBuffer` , but forgets to disable `StackBuffer` .
+        // (The heap memory is leaked.)
+        unsafe {
+            let heap_buffer = HeapBuffer::<u8>::try_with_capacity(100, &v.alloc).unwrap();
+            let ptr = &mut v.buffer as *mut StackBuffer<u8> as *mut HeapBuffer<u8>;
+            core::ptr::write(ptr, heap_buffer);
+        }
+
+        v.check_state(false);
+    }
+
+    #[test]
+    fn write() {
+        use std::io::Write;
+
+        const CHUNK: usize = 64;
+        const TOTAL: usize = 1 << 20;
+
+        let chunk: Vec<u8> = (0..CHUNK).map(|i| i as u8).collect();
+        let alloc = TestAllocator::new();
+        let mut v = SoVec::<u8, &TestAllocator>::new(&alloc);
+
+        for _ in 0..(TOTAL / CHUNK) {
+            v.write_all(&chunk).unwrap();
+        }
+        v.flush().unwrap();
+
+        assert_eq!(TOTAL, v.len());
+        assert!(v.chunks(CHUNK).all(|c| c == &chunk[..]));
+
+        // Reallocation occurs O(log TOTAL) times.
+        let log_total = (usize::BITS - TOTAL.leading_zeros()) as usize;
+        assert!(alloc.alloc_count() + alloc.realloc_count() <= log_total);
+
+        // The allocation error is returned rather than panicking.
+        v.shrink_to_fit();
+        alloc.set_failing(true);
+        let err = v.write(&chunk).unwrap_err();
+        assert_eq!(std::io::ErrorKind::OutOfMemory, err.kind());
+        assert_eq!(TOTAL, v.len());
+        alloc.set_failing(false);
+    }
+
+    #[test]
+    #[cfg(feature = "max-capacity")]
+    fn write_max_capacity() {
+        use std::io::Write;
+
+        for max_capacity in 0..(StackBuffer::<u8>::capacity() + 10) {
+            let mut v = SoVec::<u8, TestAllocator>::default();
+            v.set_max_capacity(max_capacity);
+
+            // `write` writes as many bytes as `v` can hold.
+            let buf: Vec<u8> = (0..100).collect();
+            assert_eq!(max_capacity, v.write(&buf).unwrap());
+            assert_eq!(&buf[..max_capacity], v.as_ref());
+            assert_eq!(0, v.write(&buf).unwrap());
+
+            let mut v = SoVec::<u8, TestAllocator>::default();
+            v.set_max_capacity(max_capacity);
+            let err = v.write_all(&buf).unwrap_err();
+            assert_eq!(std::io::ErrorKind::WriteZero, err.kind());
+            assert_eq!(&buf[..max_capacity], v.as_ref());
         }
+    }
+
+    #[test]
+    fn get_or() {
+        let mut v = SoVec::<String, TestAllocator>::default();
+        let default = "default".to_string();
+        assert_eq!("default", v.get_or(0, &default));
+
+        v.extend((0..100).map(|i| i.to_string()));
+        assert_eq!("0", v.get_or(0, &default));
+        assert_eq!("99", v.get_or(99, &default));
+        assert_eq!("default", v.get_or(100, &default));
+        assert_eq!("default", v.get_or(usize::MAX, &default));
+    }
+
+    #[test]
+    fn replace() {
+        let alloc = TestAllocator::new();
+        let mut v = SoVec::<String, &TestAllocator>::new(&alloc);
+        v.extend((0..100).map(|i| i.to_string()));
+        assert!(v.spilled());
+
+        assert_eq!("50", v.replace(50, "x".to_string()));
+        assert_eq!("x", v[50]);
+        assert_eq!("49", v[49]);
+        assert_eq!("51", v[51]);
+        assert_eq!(100, v.len());
+    }
+
+    #[test]
+    #[should_panic(expected = "replacement index (is 3) should be < len (is 3)")]
+    fn replace_out_of_bounds() {
+        let mut v = SoVec::<u8, std::alloc::System>::default();
+        v.extend(0..3);
+        let _ = v.replace(3, 0);
+    }
+
+    #[test]
+    fn modify() {
+        let mut v = SoVec::<String, TestAllocator>::default();
+        v.extend((0..100).map(|i| i.to_string()));
+
+        let len = v.modify(10, |s| {
+            s.push('!');
+            s.len()
+        });
+        assert_eq!(Some(3), len);
+        assert_eq!("10!", v[10]);
+
+        assert_eq!(None, v.modify(100, |_| unreachable!()));
+        assert_eq!(100, v.len());
+    }
+
+    #[test]
+    fn ensure_len_with() {
+        let mut v = SoVec::<String, TestAllocator>::default();
+
+        *v.ensure_len_with(10, String::new) = "10".to_string();
+        assert_eq!(11, v.len());
+        assert!(v[0..10].iter().all(|s| s.is_empty()));
+        assert_eq!("10", v[10]);
+
+        v.ensure_len_with(5, || unreachable!()).push('5');
+        assert_eq!(11, v.len());
+        assert_eq!("5", v[5]);
+    }
 
+    #[test]
+    fn drop_allocator_calls() {
+        // Inline
         {
             let alloc = TestAllocator::new();
-            let mut v =
-                SoVec::<String, TestAllocator>::with_capacity((u8::MAX as usize) + 1, alloc);
-            assert_eq!(None, v.pop());
+            {
+                let mut v = SoVec::<u8, &TestAllocator>::from(&alloc);
+                v.extend(0..StackBuffer::<u8>::capacity() as u8);
+                assert!(v.is_using_stack());
+            }
+            assert_eq!(0, alloc.alloc_count());
+            assert_eq!(0, alloc.dealloc_count());
+        }
 
-            for i in 0..=u8::MAX {
-                unsafe {
-                    v.push(i.to_string());
-                }
+        {
+            let alloc = TestAllocator::new();
+            {
+                let mut v = SoVec::<String, &TestAllocator>::from(&alloc);
+                v.extend((0..StackBuffer::<String>::capacity()).map(|i| i.to_string()));
+                assert!(v.is_using_stack());
             }
+            assert_eq!(0, alloc.alloc_count());
+            assert_eq!(0, alloc.dealloc_count());
+        }
 
-            for i in (0..=u8::MAX).rev() {
-                assert_eq!(Some(i.to_string()), v.pop());
+        // Heap
+        for len in 0..(StackBuffer::<u8>::capacity() + 10) {
+            let alloc = TestAllocator::new();
+            {
+                let mut v = SoVec::<u8, &TestAllocator>::from(&alloc);
+                v.reserve(StackBuffer::<u8>::capacity() + 1);
+                v.extend((0..len).map(|i| i as u8));
+                assert!(!v.is_using_stack());
             }
+            assert_eq!(1, alloc.dealloc_count());
+        }
 
-            assert_eq!(None, v.pop());
+        for len in 0..(StackBuffer::<String>::capacity() + 10) {
+            let alloc = TestAllocator::new();
+            {
+                let mut v = SoVec::<String, &TestAllocator>::from(&alloc);
+                v.reserve(StackBuffer::<String>::capacity() + 1);
+                v.extend((0..len).map(|i| i.to_string()));
+                assert!(!v.is_using_stack());
+            }
+            // (Feature `zeroize` reallocates by `alloc` and `dealloc` .)
+            assert_eq!(alloc.alloc_count(), alloc.dealloc_count());
         }
     }
 }
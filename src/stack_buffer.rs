@@ -31,12 +31,16 @@
 
 use crate::heap_buffer::HeapBuffer;
 use core::marker::PhantomData;
-use core::mem::{size_of, MaybeUninit};
+use core::mem::{align_of, size_of, MaybeUninit};
+use core::ptr::NonNull;
 
-type Buffer0 = usize;
-type Buffer1 = [u8; size_of::<HeapBuffer<u8>>() - 1];
+type Buffer0 = MaybeUninit<usize>;
+type Buffer1 = MaybeUninit<[u8; size_of::<HeapBuffer<u8>>() - 1]>;
 type Len = u8;
 
+/// The max length `StackBuffer` can hold. ( `u8::MAX` is reserved as the sentinel.)
+const MAX_LEN: usize = (u8::MAX - 1) as usize;
+
 #[repr(C)]
 pub struct StackBuffer<T> {
     _buf0: Buffer0,
@@ -48,13 +52,11 @@ pub struct StackBuffer<T> {
 impl<T> StackBuffer<T> {
     /// Create a new empty instance.
     pub fn new() -> Self {
-        unsafe {
-            Self {
-                _buf0: MaybeUninit::uninit().assume_init(),
-                _buf1: MaybeUninit::uninit().assume_init(),
-                len_: 0,
-                _marker: PhantomData,
-            }
+        Self {
+            _buf0: MaybeUninit::uninit(),
+            _buf1: MaybeUninit::uninit(),
+            len_: 0,
+            _marker: PhantomData,
         }
     }
 
@@ -74,27 +76,55 @@ impl<T> StackBuffer<T> {
     pub unsafe fn set_len(&mut self, new_len: usize) {
         debug_assert!(self.is_available());
         debug_assert!(new_len <= Self::capacity());
-        debug_assert!(new_len <= u8::MAX as usize);
+        debug_assert!(new_len <= MAX_LEN);
         self.len_ = new_len as u8;
     }
 
     /// Returns the max number of the elements `StackBuffer` can hold.
+    ///
+    /// The length is stored in `u8` and `u8::MAX` is reserved as the sentinel meaning
+    /// `self` is disabled (i.e. `HeapBuffer` is used instead.)
+    /// Then, the capacity is `MAX_LEN` (= `u8::MAX - 1` ) at most even if more elements
+    /// fit in the buffer. (e.g. if `T` is a zero sized type.)
+    ///
+    /// The buffer is aligned as `usize` . If the alignment of `T` is greater than that,
+    /// the capacity is 0; i.e. the elements are always stored in `HeapBuffer` .
     pub const fn capacity() -> usize {
-        (size_of::<Buffer0>() + size_of::<Buffer1>()) / size_of::<T>()
+        let size = size_of::<Buffer0>() + size_of::<Buffer1>();
+
+        if align_of::<Buffer0>() < align_of::<T>() {
+            0
+        } else if size_of::<T>() == 0 || MAX_LEN < size / size_of::<T>() {
+            MAX_LEN
+        } else {
+            size / size_of::<T>()
+        }
     }
 
     /// Returns a raw pointer to the buffer.
+    ///
+    /// If the capacity is 0, returns a dangling pointer which is aligned properly instead.
     pub fn as_ptr(&self) -> *const T {
         debug_assert!(self.is_available());
-        let ptr = &self._buf0 as *const usize;
-        ptr as *const T
+
+        if Self::capacity() == 0 {
+            NonNull::dangling().as_ptr()
+        } else {
+            self._buf0.as_ptr() as *const T
+        }
     }
 
     /// Returns a raw pointer to the buffer.
+    ///
+    /// If the capacity is 0, returns a dangling pointer which is aligned properly instead.
     pub fn as_mut_ptr(&mut self) -> *mut T {
         debug_assert!(self.is_available());
-        let ptr = &mut self._buf0 as *mut usize;
-        ptr as *mut T
+
+        if Self::capacity() == 0 {
+            NonNull::dangling().as_ptr()
+        } else {
+            self._buf0.as_mut_ptr() as *mut T
+        }
     }
 
     /// Returns true if `self` can be used, or false.
@@ -120,7 +150,6 @@ impl<T> Drop for StackBuffer<T> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use core::mem::align_of;
 
     #[test]
     fn size() {
@@ -163,4 +192,29 @@ mod tests {
             StackBuffer::<Bar>::capacity()
         );
     }
+
+    #[test]
+    fn capacity_max_len() {
+        assert_eq!(MAX_LEN, StackBuffer::<()>::capacity());
+        assert_eq!(u8::MAX as usize - 1, StackBuffer::<()>::capacity());
+
+        let mut buffer = StackBuffer::<()>::new();
+        unsafe { buffer.set_len(MAX_LEN) };
+        assert!(buffer.is_available());
+        assert_eq!(MAX_LEN, buffer.len());
+
+        unsafe { buffer.set_len(0) };
+    }
+
+    #[test]
+    fn capacity_over_aligned() {
+        #[repr(align(64))]
+        #[allow(dead_code)]
+        struct Aligned(u8);
+
+        assert_eq!(0, StackBuffer::<Aligned>::capacity());
+
+        let buffer = StackBuffer::<Aligned>::new();
+        assert_eq!(0, buffer.as_ptr() as usize % 64);
+    }
 }
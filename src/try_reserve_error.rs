@@ -0,0 +1,92 @@
+// Copyright 2020 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0"
+//
+// This is part of mouse-sovec
+//
+//  mouse-sovec is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sovec is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sovec.  If not, see <http://www.gnu.org/licenses/>.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::alloc::Layout;
+use core::fmt;
+use std::alloc::handle_alloc_error;
+
+/// The error type for `try_reserve` and the other fallible methods of `SoVec` .
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryReserveError {
+    /// The requested capacity exceeds the max size `SoVec` can allocate.
+    CapacityOverflow,
+    /// The allocator returned an error.
+    AllocError {
+        /// The layout passed to the allocator.
+        layout: Layout,
+    },
+    /// The requested capacity exceeds the max capacity set by `SoVec::set_max_capacity` .
+    ///
+    /// This is never returned unless feature `max-capacity` is enabled.
+    MaxCapacityExceeded {
+        /// The max capacity.
+        max_capacity: usize,
+    },
+}
+
+impl TryReserveError {
+    /// Panics or aborts as the infallible methods do.
+    ///
+    /// - `CapacityOverflow` panics.
+    /// - `AllocError` calls `std::alloc::handle_alloc_error` .
+    /// - `MaxCapacityExceeded` panics.
+    pub(crate) fn handle(self) -> ! {
+        match self {
+            Self::CapacityOverflow => panic!("Allocating memory size is too large."),
+            Self::AllocError { layout } => handle_alloc_error(layout),
+            Self::MaxCapacityExceeded { max_capacity } => panic!(
+                "Requested capacity exceeds the max capacity {}.",
+                max_capacity
+            ),
+        }
+    }
+}
+
+impl fmt::Display for TryReserveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("memory allocation failed")?;
+        match self {
+            Self::CapacityOverflow => {
+                f.write_str(" because the computed capacity exceeded the collection's maximum")
+            }
+            Self::AllocError { .. } => {
+                f.write_str(" because the memory allocator returned an error")
+            }
+            Self::MaxCapacityExceeded { max_capacity } => write!(
+                f,
+                " because the requested capacity exceeded the max capacity {}",
+                max_capacity
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TryReserveError {}
@@ -0,0 +1,54 @@
+// Copyright 2020 Shin Yoshida
+//
+// "LGPL-3.0-or-later OR Apache-2.0"
+//
+// This is part of mouse-sovec
+//
+//  mouse-sovec is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Lesser General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//
+//  mouse-sovec is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Lesser General Public License for more details.
+//
+//  You should have received a copy of the GNU Lesser General Public License
+//  along with mouse-sovec.  If not, see <http://www.gnu.org/licenses/>.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::alloc::{GlobalAlloc, Layout};
+use std::alloc::System;
+
+/// `GlobalAlloc` which can tell the actual size of the allocated memory.
+///
+/// Allocators often round the requested size up to a size class. `SoVec::with_capacity_usable`
+/// uses the extra bytes as well if the allocator implements this trait.
+///
+/// # Safety
+///
+/// The memory must be able to be deallocated or reallocated with the layout whose size is any
+/// value between the requested size and the size `usable_size` returns.
+pub unsafe trait UsableSize: GlobalAlloc {
+    /// Returns the size of the memory `ptr` points to, which has been allocated with `layout` .
+    ///
+    /// The default implementation returns `layout.size()` ; i.e. no extra byte is used.
+    fn usable_size(&self, ptr: *mut u8, layout: Layout) -> usize {
+        let _ = ptr;
+        layout.size()
+    }
+}
+
+unsafe impl UsableSize for System {}